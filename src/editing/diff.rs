@@ -0,0 +1,81 @@
+use similar::TextDiff;
+
+/// Builds a unified diff between the original and edited text of a file,
+/// in a form that `git apply` and `patch -p1` understand
+///
+/// # Arguments
+///
+/// * `path_name` - The path of the file, used for the `---` and `+++` headers
+/// * `original` - The text of the file before editing
+/// * `edited` - The text of the file after editing
+/// * `context` - The number of unchanged lines to show around each change
+///
+/// # Returns
+///
+/// The diff as a String, which is empty if the texts are the same
+pub fn unified_diff(path_name: &str, original: &str, edited: &str, context: usize) -> String {
+    if original == edited {
+        return String::new();
+    }
+    let path_name = path_name.trim_start_matches("./");
+    TextDiff::from_lines(original, edited)
+        .unified_diff()
+        .context_radius(context)
+        .header(&format!("a/{}", path_name), &format!("b/{}", path_name))
+        .to_string()
+}
+
+/// Builds a diff that renames a file as well as editing it, in the git form
+/// that `git apply` understands
+///
+/// # Arguments
+///
+/// * `from` - The path of the file before renaming
+/// * `to` - The path of the file after renaming
+/// * `original` - The text of the file before editing
+/// * `edited` - The text of the file after editing
+/// * `context` - The number of unchanged lines to show around each change
+///
+/// # Returns
+///
+/// The diff as a String, which just renames the file if the texts are the same
+pub fn renamed_diff(from: &str, to: &str, original: &str, edited: &str, context: usize) -> String {
+    let (from, to) = (from.trim_start_matches("./"), to.trim_start_matches("./"));
+    let mut ret = format!("diff --git a/{} b/{}\nrename from {}\nrename to {}\n", from, to, from, to);
+    if original != edited {
+        ret += &TextDiff::from_lines(original, edited)
+            .unified_diff()
+            .context_radius(context)
+            .header(&format!("a/{}", from), &format!("b/{}", to))
+            .to_string();
+    }
+    ret
+}
+
+#[test]
+fn test_unified_diff() {
+    let original = "a\nb\nc\nd\ne\n";
+    let edited = "a\nb\nC\nd\ne\n";
+    assert_eq!(unified_diff("./x.java", original, original, 3), "");
+    assert_eq!(
+        unified_diff("./x.java", original, edited, 1),
+        "--- a/x.java\n+++ b/x.java\n@@ -2,3 +2,3 @@\n b\n-c\n+C\n d\n"
+    );
+    assert_eq!(
+        unified_diff("x.java", "a", "b", 3),
+        "--- a/x.java\n+++ b/x.java\n@@ -1 +1 @@\n-a\n\\ No newline at end of file\n+b\n\\ No newline at end of file\n"
+    );
+}
+
+#[test]
+fn test_renamed_diff() {
+    assert_eq!(
+        renamed_diff("./x.java", "./y.java", "a\n", "b\n", 3),
+        "diff --git a/x.java b/y.java\nrename from x.java\nrename to y.java\n\
+         --- a/x.java\n+++ b/y.java\n@@ -1 +1 @@\n-a\n+b\n"
+    );
+    assert_eq!(
+        renamed_diff("x.java", "y.java", "a\n", "a\n", 3),
+        "diff --git a/x.java b/y.java\nrename from x.java\nrename to y.java\n"
+    );
+}
@@ -0,0 +1,81 @@
+use std::{fs, io::Write, path::Path};
+
+use crate::error::Error;
+
+/// Replaces the contents of a file by writing them to a temporary file in the
+/// same directory and renaming it over the original, so that the original is
+/// never left half written. A symlink is written through, replacing the file it
+/// points to rather than the link itself
+///
+/// # Arguments
+///
+/// * `path` - The path of the file to replace
+/// * `contents` - The new contents of the file
+/// * `backup` - An optional suffix, like `.bak`, for keeping a copy of the original
+///
+/// # Returns
+///
+/// A Result<(), Error>, which will Err if the file could not be
+/// backed up or written, in which case the original is left untouched
+pub fn write_atomic(path: &Path, contents: &str, backup: Option<&str>) -> Result<(), Error> {
+    let target = fs::canonicalize(path).map_err(Error::io(path))?;
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let permissions = fs::metadata(path).map_err(Error::io(path))?.permissions();
+    let mut file = tempfile::NamedTempFile::new_in(dir).map_err(Error::io(dir))?;
+    file.write_all(contents.as_bytes()).map_err(Error::io(file.path()))?;
+    file.as_file().sync_all().map_err(Error::io(file.path()))?;
+    fs::set_permissions(file.path(), permissions).map_err(Error::io(file.path()))?;
+    if let Some(suffix) = backup {
+        let mut backup_path = path.as_os_str().to_owned();
+        backup_path.push(suffix);
+        fs::copy(path, &backup_path).map_err(Error::io(&backup_path))?;
+    }
+    file.persist(&target).map_err(|x| Error::io(&target)(x.error))?;
+    Ok(())
+}
+
+#[test]
+fn test_write_atomic() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("a.java");
+    fs::write(&path, "old")?;
+    write_atomic(&path, "new", None)?;
+    assert_eq!(fs::read_to_string(&path)?, "new");
+    assert!(!dir.path().join("a.java.bak").exists());
+    write_atomic(&path, "newer", Some(".bak"))?;
+    assert_eq!(fs::read_to_string(&path)?, "newer");
+    assert_eq!(fs::read_to_string(dir.path().join("a.java.bak"))?, "new");
+    assert_eq!(fs::read_dir(dir.path())?.count(), 2);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_write_atomic_permissions() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("run.sh");
+    fs::write(&path, "old")?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+    write_atomic(&path, "new", None)?;
+    assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o755);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_write_atomic_symlink() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    fs::create_dir(dir.path().join("src"))?;
+    let target = dir.path().join("src").join("a.c");
+    let link = dir.path().join("link.c");
+    fs::write(&target, "old")?;
+    std::os::unix::fs::symlink(&target, &link)?;
+    write_atomic(&link, "new", None)?;
+    assert!(fs::symlink_metadata(&link)?.file_type().is_symlink());
+    assert_eq!(fs::read_to_string(&target)?, "new");
+    Ok(())
+}
@@ -0,0 +1,337 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use similar::{DiffOp, TextDiff};
+
+use crate::error::Error;
+
+/// The file, within the directory spidior is run from, that records what each run changed
+pub const JOURNAL: &str = ".spidior.journal";
+
+/// A single change to the text of a file, positioned within the edited text
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub offset: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+/// Something done to the filesystem that can be undone
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Action {
+    Write {
+        path: String,
+        original_hash: u64,
+        edited_hash: u64,
+        edits: Vec<Edit>,
+    },
+    Rename {
+        from: String,
+        to: String,
+    },
+}
+
+/// A line of the journal, tying an action to the run that performed it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    run: u128,
+    #[serde(flatten)]
+    action: Action,
+}
+
+/// Records the actions of a single run to a journal, one line per action,
+/// so that even an interrupted run can be undone
+///
+/// Each action is recorded before it is performed, so an action that was
+/// recorded but never performed is skipped when undoing.
+pub struct Journal {
+    file: String,
+    run: u128,
+}
+
+impl Journal {
+    /// Creates a journal for a new run
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The path of the journal file, which is created when first recorded to
+    ///
+    /// # Returns
+    ///
+    /// A Journal that appends to the given file
+    pub fn new(file: &str) -> Self {
+        Self {
+            file: file.to_string(),
+            run: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Records that a file is about to be rewritten
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file
+    /// * `original` - The contents of the file before it is written
+    /// * `edited` - The contents of the file after it is written
+    pub fn record_write(&self, path: &str, original: &str, edited: &str) -> Result<(), Error> {
+        self.record(Action::Write {
+            path: path.to_string(),
+            original_hash: hash(original),
+            edited_hash: hash(edited),
+            edits: edits_between(original, edited),
+        })
+    }
+
+    /// Records that a file is about to be renamed
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The old path of the file
+    /// * `to` - The new path of the file
+    pub fn record_rename(&self, from: &str, to: &str) -> Result<(), Error> {
+        self.record(Action::Rename {
+            from: from.to_string(),
+            to: to.to_string(),
+        })
+    }
+
+    fn record(&self, action: Action) -> Result<(), Error> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file)
+            .map_err(Error::io(&self.file))?;
+        let entry = Entry { run: self.run, action };
+        writeln!(file, "{}", serde_json::to_string(&entry).map_err(corrupt)?).map_err(Error::io(&self.file))?;
+        Ok(())
+    }
+}
+
+/// Reverts every action of the last run recorded in a journal, then removes them from it
+///
+/// # Arguments
+///
+/// * `file` - The path of the journal file
+///
+/// # Returns
+///
+/// A Result<Vec<Action>, Error>, where on success, it returns the actions
+/// that were reverted. It will Err without changing anything if there is nothing to
+/// undo, or if any of the files have changed since the run.
+pub fn undo(file: &str) -> Result<Vec<Action>, Error> {
+    let nothing = || Error::Journal("There is nothing to undo".into());
+    let text = fs::read_to_string(file).map_err(|_| nothing())?;
+    let entries = text
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<Entry>, _>>()
+        .map_err(corrupt)?;
+    let run = entries.last().ok_or_else(nothing)?.run;
+    let (last, rest): (Vec<Entry>, Vec<Entry>) = entries.into_iter().partition(|e| e.run == run);
+    let actions: Vec<Action> = last.into_iter().rev().map(|e| e.action).collect();
+    let mut reverted = Vec::new();
+    let mut moved = HashMap::new();
+    for action in &actions {
+        let revert = revert(action, &moved)?;
+        if let (Action::Rename { from, to }, Revert::Rename) = (action, &revert) {
+            moved.insert(from.clone(), to.clone());
+        }
+        reverted.push(revert);
+    }
+    for (action, revert) in actions.iter().zip(reverted) {
+        match (action, revert) {
+            (Action::Write { path, .. }, Revert::Write(contents)) => {
+                super::files::write_atomic(Path::new(path), &contents, None)?
+            }
+            (Action::Rename { from, to }, Revert::Rename) => fs::rename(to, from).map_err(Error::io(to))?,
+            _ => {}
+        }
+    }
+    let mut remaining = String::new();
+    for entry in rest {
+        remaining += &serde_json::to_string(&entry).map_err(corrupt)?;
+        remaining.push('\n');
+    }
+    if remaining.is_empty() {
+        fs::remove_file(file).map_err(Error::io(file))?;
+    } else {
+        fs::write(file, remaining).map_err(Error::io(file))?;
+    }
+    Ok(actions)
+}
+
+/// What it takes to revert an action
+enum Revert {
+    /// Writing the original contents back to the file
+    Write(String),
+    /// Renaming the file back
+    Rename,
+    /// Nothing, as the action was recorded but never performed
+    Nothing,
+}
+
+/// Checks that an action can be reverted, working out the original contents of written files,
+/// which may since have been moved by a later rename
+fn revert(action: &Action, moved: &HashMap<String, String>) -> Result<Revert, Error> {
+    match action {
+        Action::Write {
+            path,
+            original_hash,
+            edited_hash,
+            edits,
+        } => {
+            let current = moved.get(path).unwrap_or(path);
+            let mut text = fs::read_to_string(current).map_err(Error::io(current))?;
+            if hash(&text) == *original_hash && hash(&text) != *edited_hash {
+                return Ok(Revert::Nothing);
+            }
+            if hash(&text) != *edited_hash {
+                return Err(Error::Journal(format!(
+                    "Refusing to undo, {} has changed since it was edited",
+                    path
+                )));
+            }
+            for edit in edits.iter().rev() {
+                let end = edit.offset + edit.inserted.len();
+                if text.get(edit.offset..end) != Some(edit.inserted.as_str()) {
+                    return Err(Error::Journal(format!("Refusing to undo, the journal for {} is corrupt", path)));
+                }
+                text.replace_range(edit.offset..end, &edit.removed);
+            }
+            if hash(&text) != *original_hash {
+                return Err(Error::Journal(format!("Refusing to undo, the journal for {} is corrupt", path)));
+            }
+            Ok(Revert::Write(text))
+        }
+        Action::Rename { from, to } => {
+            if !Path::new(to).exists() && Path::new(from).exists() {
+                return Ok(Revert::Nothing);
+            }
+            if !Path::new(to).exists() {
+                return Err(Error::Journal(format!("Refusing to undo, {} no longer exists", to)));
+            }
+            if Path::new(from).exists() {
+                return Err(Error::Journal(format!("Refusing to undo, {} already exists", from)));
+            }
+            Ok(Revert::Rename)
+        }
+    }
+}
+
+fn corrupt(err: serde_json::Error) -> Error {
+    Error::Journal(format!("The journal is corrupt: {}", err))
+}
+
+/// Hashes text with 64 bit FNV-1a, which is stable across builds unlike the std hasher
+fn hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Works out the line-based edits that turn one text into another
+fn edits_between(original: &str, edited: &str) -> Vec<Edit> {
+    let diff = TextDiff::from_lines(original, edited);
+    let offsets = |lines: &[&str]| {
+        lines
+            .iter()
+            .scan(0, |acc, l| {
+                let start = *acc;
+                *acc += l.len();
+                Some(start)
+            })
+            .chain(std::iter::once(lines.iter().map(|l| l.len()).sum()))
+            .collect::<Vec<usize>>()
+    };
+    let (old, new) = (offsets(diff.old_slices()), offsets(diff.new_slices()));
+    diff.ops()
+        .iter()
+        .filter(|op| !matches!(op, DiffOp::Equal { .. }))
+        .map(|op| {
+            let (old_range, new_range) = (op.old_range(), op.new_range());
+            Edit {
+                offset: new[new_range.start],
+                removed: original[old[old_range.start]..old[old_range.end]].to_string(),
+                inserted: edited[new[new_range.start]..new[new_range.end]].to_string(),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_edits_between() {
+    let original = "a\nb\nc\nd\n";
+    let edited = "a\nB\nc\nd\ne\n";
+    assert_eq!(
+        edits_between(original, edited),
+        vec![
+            Edit { offset: 2, removed: "b\n".into(), inserted: "B\n".into() },
+            Edit { offset: 8, removed: "".into(), inserted: "e\n".into() },
+        ]
+    );
+    assert!(edits_between(original, original).is_empty());
+}
+
+#[test]
+fn test_undo() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let journal_path = dir.path().join("journal").to_string_lossy().to_string();
+    let a = dir.path().join("a.java").to_string_lossy().to_string();
+    let b = dir.path().join("b.java").to_string_lossy().to_string();
+    fs::write(&a, "one\ntwo\n")?;
+
+    let first = Journal::new(&journal_path);
+    first.record_write(&a, "one\ntwo\n", "one\n2\n")?;
+    fs::write(&a, "one\n2\n")?;
+    let second = Journal { file: journal_path.clone(), run: first.run + 1 };
+    second.record_write(&a, "one\n2\n", "1\n2\n")?;
+    fs::write(&a, "1\n2\n")?;
+    second.record_rename(&a, &b)?;
+    fs::rename(&a, &b)?;
+
+    assert_eq!(undo(&journal_path)?.len(), 2);
+    assert_eq!(fs::read_to_string(&a)?, "one\n2\n");
+    assert!(!Path::new(&b).exists());
+
+    fs::write(&a, "changed\n")?;
+    assert!(undo(&journal_path).is_err());
+    fs::write(&a, "one\n2\n")?;
+    assert_eq!(undo(&journal_path)?.len(), 1);
+    assert_eq!(fs::read_to_string(&a)?, "one\ntwo\n");
+    assert!(undo(&journal_path).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_undo_unperformed() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let journal_path = dir.path().join("journal").to_string_lossy().to_string();
+    let a = dir.path().join("a.java").to_string_lossy().to_string();
+    let b = dir.path().join("b.java").to_string_lossy().to_string();
+    let c = dir.path().join("c.java").to_string_lossy().to_string();
+    fs::write(&a, "one\n")?;
+    fs::write(&b, "two\n")?;
+
+    // The run was interrupted after recording the write of b and the rename of a, but before performing them
+    let journal = Journal::new(&journal_path);
+    journal.record_write(&a, "one\n", "1\n")?;
+    fs::write(&a, "1\n")?;
+    journal.record_write(&b, "two\n", "2\n")?;
+    journal.record_rename(&a, &c)?;
+
+    assert_eq!(undo(&journal_path)?.len(), 3);
+    assert_eq!(fs::read_to_string(&a)?, "one\n");
+    assert_eq!(fs::read_to_string(&b)?, "two\n");
+    assert!(!Path::new(&c).exists());
+    Ok(())
+}
@@ -1 +1,4 @@
-pub mod textbuffer;
\ No newline at end of file
+pub mod diff;
+pub mod files;
+pub mod journal;
+pub mod textbuffer;
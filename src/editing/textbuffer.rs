@@ -1,6 +1,7 @@
-use std::error::Error;
+use crate::error::Error;
 /// A buffer for holding text, supporting operations
 /// for replacement of text as well as appending
+#[derive(Default)]
 pub struct TextBuffer {
     buf: String,
 }
@@ -26,7 +27,7 @@ impl TextBuffer {
     ///
     /// # Returns
     ///
-    /// A Result<String, Box<dyn Error>>, where on success, it returns
+    /// A Result<String, Error>, where on success, it returns
     /// what was erased. It will Err if you attempt to replace more text
     /// than exists in the buffer.
     pub fn replace(
@@ -34,9 +35,9 @@ impl TextBuffer {
         start: usize,
         length: usize,
         replacement: &str,
-    ) -> Result<String, Box<dyn Error>> {
+    ) -> Result<String, Error> {
         if self.buf.len() < start + length {
-            return Err("Replacing more of the string than exists".into());
+            return Err(Error::Edit("Replacing more of the string than exists".into()));
         }
         let erased = self.buf[start..start + length].to_string();
         self.buf = format!(
@@ -59,16 +60,16 @@ impl TextBuffer {
     ///
     /// # Returns
     ///
-    /// A Result<String, Box<dyn Error>>, where on success, it returns
+    /// A Result<String, Error>, where on success, it returns
     /// the desired text. It will Err if you attempt to read more text
     /// than exists in the buffer.
     pub fn get(
         &mut self,
         start: usize,
         length: usize,
-    ) -> Result<String, Box<dyn Error>> {
+    ) -> Result<String, Error> {
         if self.buf.len() < start + length {
-            return Err("Reading more of the string than exists".into());
+            return Err(Error::Edit("Reading more of the string than exists".into()));
         }
         Ok(self.buf[start..start + length].to_string())
     }
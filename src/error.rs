@@ -0,0 +1,251 @@
+//! The errors that can occur while parsing queries and editing files
+
+use std::{fmt, io};
+
+use lalrpop_util::ParseError;
+
+/// Everything that can go wrong within spidior
+///
+/// Errors in parsing a query carry the column they occurred at, which is the
+/// character offset into the query, starting from 0.
+#[derive(Debug)]
+pub enum Error {
+    /// The query does not have the shape of a command, such as when it is missing a delimiter
+    Command { message: String, column: usize },
+    /// The location of a command could not be parsed
+    Location { message: String, column: usize },
+    /// The regex of a command could not be parsed
+    Regex { message: String, column: usize },
+    /// The replacement of a command could not be parsed
+    Replacement { message: String, column: usize },
+    /// The criteria of a `[[...]]` query on the identifiers parsed from the source could not be parsed
+    Query { message: String, column: usize },
+    /// A command within a script could not be parsed
+    Script { line: usize, source: Box<Error> },
+    /// A file could not be read or written
+    Io { path: String, source: io::Error },
+    /// The journal could not be read, or refused to undo a run
+    Journal(String),
+    /// An edit could not be made, such as one reaching past the end of the text being edited
+    Edit(String),
+}
+
+impl Error {
+    /// Gets the column within the query that a parse error occurred at
+    ///
+    /// # Returns
+    ///
+    /// The character offset into the query, or None if this is not a parse error
+    pub fn column(&self) -> Option<usize> {
+        match self {
+            Error::Command { column, .. }
+            | Error::Location { column, .. }
+            | Error::Regex { column, .. }
+            | Error::Replacement { column, .. }
+            | Error::Query { column, .. } => Some(*column),
+            Error::Script { source, .. } => source.column(),
+            _ => None,
+        }
+    }
+
+    /// Moves the column of a parse error along, for when the part of the query
+    /// that failed to parse started partway into the query
+    ///
+    /// # Arguments
+    ///
+    /// * `by` - The offset of the part within the query
+    pub(crate) fn shift(self, by: usize) -> Self {
+        self.map_column(|column| column + by)
+    }
+
+    /// Converts the column of a parse error from the byte offset the parsers work
+    /// in to the character offset it is reported as
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The query the error occurred in
+    pub(crate) fn in_chars(self, query: &str) -> Self {
+        self.map_column(|column| query.get(..column).unwrap_or(query).chars().count())
+    }
+
+    fn map_column(mut self, f: impl FnOnce(usize) -> usize) -> Self {
+        match &mut self {
+            Error::Command { column, .. }
+            | Error::Location { column, .. }
+            | Error::Regex { column, .. }
+            | Error::Replacement { column, .. }
+            | Error::Query { column, .. } => *column = f(*column),
+            _ => {}
+        }
+        self
+    }
+
+    /// Renders this error against the query it came from, with a caret under the
+    /// column it occurred at and a hint when it looks like a common mistake, such as
+    ///
+    /// ```text
+    /// Invalid command at column 7: Did not find an unescaped /!
+    ///   %s/a/b
+    ///         ^
+    /// hint: did you mean `%s/a/b/`?
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The query that failed to parse, or the line of a script for a Script error
+    ///
+    /// # Returns
+    ///
+    /// The rendered error, over several lines
+    pub fn render(&self, query: &str) -> String {
+        if let Error::Script { line, source } = self {
+            return format!("Line {}: {}", line, source.render(query));
+        }
+        let mut ret = self.to_string();
+        if let Some(column) = self.column() {
+            ret += &format!("\n  {}\n  {}^", query, " ".repeat(column));
+        }
+        if let Some(hint) = self.hint(query) {
+            ret += &format!("\nhint: {}", hint);
+        }
+        ret
+    }
+
+    /// Suggests a fix for a parse error that looks like a common mistake
+    fn hint(&self, query: &str) -> Option<String> {
+        let unclosed = |open: char, close: char| query.matches(open).count() > query.matches(close).count();
+        match self {
+            Error::Command { message, .. } if message.starts_with("Did not find an unescaped ") => {
+                let delimiter = message.chars().nth("Did not find an unescaped ".len())?;
+                let fixed = format!("{}{}", query, delimiter);
+                Some(if crate::regexparser::parse(&fixed).is_ok() {
+                    format!("did you mean `{}`?", fixed)
+                } else {
+                    format!("each part of a command ends with a {0}, and a {0} within a part is escaped as \\{0}", delimiter)
+                })
+            }
+            Error::Command { message, .. } if message.starts_with("Did not find a command") => {
+                Some("queries look like %s/find/replace/, %/find/ or {main}d, where % is everywhere".into())
+            }
+            Error::Command { message, .. } if message.starts_with("Expected nothing or a g") => {
+                Some("only a g can follow the replacement, so escape any delimiter within it with a \\".into())
+            }
+            Error::Regex { .. } if unclosed('(', ')') => Some("every ( must be closed by a )".into()),
+            Error::Regex { .. } if unclosed('[', ']') => Some("every [ must be closed by a ]".into()),
+            Error::Regex { .. } => Some("escape characters like ( ) [ ] | * + . and $ with a \\ to match them".into()),
+            Error::Location { message, .. } if message.contains("a number") => {
+                Some("line and character ranges need both bounds, as in l0-10".into())
+            }
+            Error::Location { .. } => Some("combine locations with & and |, as in <.java>&{main}".into()),
+            Error::Query { .. } => Some("query criteria look like [[type=int,name=x]]".into()),
+            _ => None,
+        }
+    }
+
+    /// Creates an error for a file that could not be read or written
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file
+    pub(crate) fn io(path: impl AsRef<std::path::Path>) -> impl FnOnce(io::Error) -> Self {
+        let path = path.as_ref().to_string_lossy().to_string();
+        move |source| Error::Io { path, source }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Command { message, column } => write!(f, "Invalid command at column {}: {}", column + 1, message),
+            Error::Location { message, column } => write!(f, "Invalid location at column {}: {}", column + 1, message),
+            Error::Regex { message, column } => write!(f, "Invalid regex at column {}: {}", column + 1, message),
+            Error::Replacement { message, column } => {
+                write!(f, "Invalid replacement at column {}: {}", column + 1, message)
+            }
+            Error::Query { message, column } => write!(f, "Invalid query at column {}: {}", column + 1, message),
+            Error::Script { line, source } => write!(f, "Line {}: {}", line, source),
+            Error::Io { path, source } => write!(f, "{}: {}", path, source),
+            Error::Journal(message) | Error::Edit(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Script { source, .. } => Some(source.as_ref()),
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Describes an error from one of the lalrpop parsers
+///
+/// # Arguments
+///
+/// * `err` - The error from the parser
+///
+/// # Returns
+///
+/// A tuple of the message and the offset into the parsed text the error occurred at
+pub(crate) fn describe<T: fmt::Display>(err: ParseError<usize, T, (usize, &'static str)>) -> (String, usize) {
+    let expected = |expected: Vec<String>| {
+        let mut names: Vec<&str> = expected.iter().map(|x| token_name(x)).collect();
+        names.dedup();
+        match names.len() {
+            0 => String::new(),
+            1 => format!(", expected {}", names[0]),
+            _ => format!(", expected one of {}", names.join(", ")),
+        }
+    };
+    match err {
+        ParseError::InvalidToken { location } => ("Invalid token".to_string(), location),
+        ParseError::UnrecognizedEOF { location, expected: e } => {
+            (format!("Unexpected end{}", expected(e)), location)
+        }
+        ParseError::UnrecognizedToken { token: (l, t, _), expected: e } => {
+            (format!("Unexpected `{}`{}", t, expected(e)), l)
+        }
+        ParseError::ExtraToken { token: (l, t, _) } => (format!("Unexpected `{}`", t), l),
+        ParseError::User { error: (location, message) } => (message.to_string(), location),
+    }
+}
+
+/// Names the tokens that lalrpop describes by their regex
+fn token_name(token: &str) -> &str {
+    match token {
+        r##"r#"."#"## => "any character",
+        r##"r#"\\\\."#"## => "an escaped character",
+        r##"r#"[0-9]*"#"## => "a number",
+        r##"r#"[^=,]*"#"## => "a name",
+        r##"r#"<[^%:<>]*>"#"## => "a path like <src/>",
+        x if x.starts_with(r#"r#"\\{/"#) => "a function regex like {/^handle/}",
+        x if x.starts_with(r#"r#"\\{"#) => "a function like {main}",
+        x => x,
+    }
+}
+
+#[test]
+fn test_render() {
+    let render = |query: &str| crate::regexparser::parse(query).unwrap_err().render(query);
+    assert_eq!(
+        render("%s/a/b"),
+        "Invalid command at column 7: Did not find an unescaped /!\n  %s/a/b\n        ^\nhint: did you mean `%s/a/b/`?"
+    );
+    assert_eq!(
+        render("{é}&l-3d"),
+        "Invalid location at column 6: Unexpected `-`, expected a number\n  {é}&l-3d\n       ^\n\
+         hint: line and character ranges need both bounds, as in l0-10"
+    );
+    assert!(render("%s/(a/b/").ends_with("\n       ^\nhint: every ( must be closed by a )"));
+    assert!(render("%s/a/b/c/").ends_with("hint: only a g can follow the replacement, so escape any delimiter within it with a \\"));
+}
+
+#[test]
+fn test_render_token_names() {
+    for query in ["{main}&s/a/b/", "%s/a(/b/", "%s/[[type=]]/b/", "<src/>&(s/a/b/", "é&s/a/b/"] {
+        let rendered = crate::regexparser::parse(query).unwrap_err().render(query);
+        assert!(!rendered.contains("r#\""), "{}", rendered);
+    }
+}
@@ -1,6 +1,7 @@
 //! Provides the parser for "c-like" languages, including C and Java
 
-use super::parsing::{Function, Functions, Identifier, Identifiers};
+use super::parsing::{Function, Functions, Identifier, Identifiers, Role};
+use crate::nfa::matcher::byte_offsets;
 use std::collections::HashMap;
 
 /// A Functions and Identifiers parser for Clike languages,
@@ -22,6 +23,43 @@ impl Clike {
         ]
         .contains(&x)
     }
+
+    fn is_container(x: &str) -> bool {
+        ["class", "struct", "interface", "enum"].contains(&x)
+    }
+
+    /// Gets the qualified name of the innermost container in a set of scopes
+    fn qualified(scopes: &[Option<(String, usize)>]) -> Option<String> {
+        scopes.iter().rev().find_map(|scope| scope.as_ref().map(|(name, _)| name.clone()))
+    }
+
+    /// Determines the role of a use of an identifier by looking at
+    /// what follows it, so that `x = 1` and `x += 1` are assignments
+    fn use_role(text: &str, i: usize) -> Role {
+        let mut rest = text.get(i..).unwrap_or("").trim_start().chars();
+        match (rest.next(), rest.next()) {
+            (Some('='), Some('=')) => Role::Use,
+            (Some('='), _) => Role::Assignment,
+            (Some(c), Some('=')) if "+-*/%&|^".contains(c) => Role::Assignment,
+            _ => Role::Use,
+        }
+    }
+}
+
+/// A scope opened by a brace, holding the identifiers declared within it
+struct Frame {
+    names: HashMap<String, String>,
+    /// The name of the class, struct, or the like if this scope is its body
+    container: Option<String>,
+}
+
+impl Frame {
+    fn new(container: Option<String>) -> Self {
+        Self {
+            names: HashMap::new(),
+            container,
+        }
+    }
 }
 
 enum FunctionFsm {
@@ -43,15 +81,41 @@ impl Functions for Clike {
     /// # Returns
     ///
     /// A Vec of Function containing information on every function
-    /// declared within text
+    /// declared within text, as well as every class, struct, interface,
+    /// or enum that functions are declared within
     fn read_functions(&self, text: &str) -> Vec<Function> {
+        let bytes = byte_offsets(text);
         let mut s = FunctionFsm::NONE;
         let mut start = 0;
         let mut end = 0;
         let mut start_body = 0;
         let mut braces = 0;
         let mut v = Vec::new();
+        let mut word = None;
+        let mut container_next = false;
+        let mut pending_container: Option<String> = None;
+        // Braces outside of function bodies, with the qualified name and
+        // start of any container, like a class, that they open
+        let mut scopes: Vec<Option<(String, usize)>> = Vec::new();
         for (i, c) in text.chars().enumerate() {
+            let in_body = matches!(s, FunctionFsm::BRACE | FunctionFsm::INNER);
+            if !in_body {
+                if c.is_alphanumeric() {
+                    word.get_or_insert(i);
+                } else if let Some(ws) = word.take() {
+                    let w = &text[bytes[ws]..bytes[i]];
+                    if container_next {
+                        pending_container = Some(w.to_string());
+                        container_next = false;
+                    } else if Self::is_container(w) {
+                        container_next = true;
+                    }
+                }
+                if c == ';' {
+                    pending_container = None;
+                    container_next = false;
+                }
+            }
             match s {
                 FunctionFsm::NONE => {
                     if c.is_alphanumeric() {
@@ -100,22 +164,40 @@ impl Functions for Clike {
                         braces -= 1;
                     }
                     if braces == 0 {
-                        v.push(Function::new(text[start..end].to_string(), start_body, i + 1));
+                        let container = Self::qualified(&scopes);
+                        v.push(Function::new(text[bytes[start]..bytes[end]].to_string(), start_body, i + 1).within(container));
                         s = FunctionFsm::NONE;
                     }
                 }
             }
+            if !in_body && c == '{' && !matches!(s, FunctionFsm::BRACE) {
+                let name = pending_container.take().map(|name| match Self::qualified(&scopes) {
+                    Some(outer) => format!("{}.{}", outer, name),
+                    None => name,
+                });
+                scopes.push(name.map(|name| (name, i)));
+            } else if !in_body && c == '}' {
+                if let Some(Some((qualified, cstart))) = scopes.pop() {
+                    let (container, name) = match qualified.rfind('.') {
+                        Some(dot) => (Some(qualified[..dot].to_string()), qualified[dot + 1..].to_string()),
+                        None => (None, qualified),
+                    };
+                    v.push(Function::new_container(name, cstart, i + 1).within(container));
+                }
+            }
         }
         v
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 enum IFsm {
     NONE,
     NAME1,
     SPACE,
     NAME2,
     DOT,
+    MEMBER,
 }
 
 impl Identifiers for Clike {
@@ -128,36 +210,73 @@ impl Identifiers for Clike {
     /// # Returns
     ///
     /// A Vec of Identifier containing information on every use of
-    /// an identifier declared within the code
+    /// an identifier declared within the code, as well as every access
+    /// of a member on a receiver whose type is known
     fn read_identifiers(&self, text: &str) -> Vec<Identifier> {
+        let bytes = byte_offsets(text);
         let mut s = IFsm::NONE;
         let mut n1s = 0;
         let mut n1e = 0;
         let mut n2s = 0;
         let mut n2e;
+        let mut ms = 0;
+        let mut receiver: Option<String> = None;
         let mut v = Vec::new();
-        let mut stack = Vec::<HashMap<String, String>>::new();
-        stack.push(HashMap::new());
+        let mut parens = 0;
+        let mut pending_container: Option<String> = None;
+        let mut fields = HashMap::<String, HashMap<String, String>>::new();
+        let mut stack = Vec::<Frame>::new();
+        stack.push(Frame::new(None));
         for (i, c) in text.chars().enumerate() {
             if c == '{' {
-                stack.push(HashMap::new());
+                stack.push(Frame::new(pending_container.take()));
                 s = IFsm::NONE;
             } else if c == '}' {
                 stack.pop();
                 s = IFsm::NONE;
+            } else if c == ';' {
+                pending_container = None;
+            } else if c == '(' {
+                parens += 1;
+            } else if c == ')' && parens > 0 {
+                parens -= 1;
             }
             match s {
                 IFsm::NONE => {
                     if c == '.' {
                         s = IFsm::DOT;
+                        receiver = None;
                     } else if c.is_alphabetic() {
                         s = IFsm::NAME1;
                         n1s = i;
                     }
                 }
                 IFsm::DOT => {
-                    if c.is_whitespace() {
-                        s = IFsm::SPACE;
+                    if c.is_alphabetic() {
+                        s = IFsm::MEMBER;
+                        ms = i;
+                    } else if !c.is_whitespace() {
+                        s = IFsm::NONE;
+                    }
+                }
+                IFsm::MEMBER => {
+                    if !c.is_alphanumeric() {
+                        //Push member access
+                        let owner = receiver.take();
+                        if let Some(owner) = owner {
+                            let name = text[bytes[ms]..bytes[i]].to_string();
+                            let typ = fields
+                                .get(&owner)
+                                .and_then(|f| f.get(&name))
+                                .cloned()
+                                .unwrap_or_default();
+                            if !typ.is_empty() {
+                                receiver = Some(typ.clone());
+                            }
+                            let role = Self::use_role(text, bytes[i]);
+                            v.push(Identifier::new(name, typ, role, ms, i).with_owner(owner));
+                        }
+                        s = if c == '.' { IFsm::DOT } else { IFsm::NONE };
                     }
                 }
                 IFsm::NAME1 => {
@@ -166,16 +285,24 @@ impl Identifiers for Clike {
                         n1e = i;
                     } else if !c.is_alphanumeric() {
                         //Push declared identifier
+                        n1e = i;
+                        let name = text[bytes[n1s]..bytes[n1e]].to_string();
+                        receiver = None;
                         if c == '.' {
                             s = IFsm::DOT;
+                            if name == "this" {
+                                receiver = stack.iter().rev().find_map(|f| f.container.clone());
+                            }
                         } else {
                             s = IFsm::NONE;
                         }
-                        n1e = i;
-                        let name = text[n1s..n1e].to_string();
                         for frame in stack.iter().rev() {
-                            if let Some(typ) = frame.get(&name) {
-                                v.push(Identifier::new(name, typ.to_string(), n1s, n1e));
+                            if let Some(typ) = frame.names.get(&name) {
+                                let role = Self::use_role(text, bytes[i]);
+                                if c == '.' {
+                                    receiver = Some(typ.to_string());
+                                }
+                                v.push(Identifier::new(name, typ.to_string(), role, n1s, n1e));
                                 break;
                             }
                         }
@@ -188,10 +315,11 @@ impl Identifiers for Clike {
                     } else if !c.is_whitespace() {
                         //Push declared identifier
                         s = IFsm::NONE;
-                        let name = text[n1s..n1e].to_string();
+                        let name = text[bytes[n1s]..bytes[n1e]].to_string();
                         for frame in stack.iter().rev() {
-                            if let Some(typ) = frame.get(&name) {
-                                v.push(Identifier::new(name.clone(), typ.to_string(), n1s, n1e));
+                            if let Some(typ) = frame.names.get(&name) {
+                                let role = Self::use_role(text, bytes[i]);
+                                v.push(Identifier::new(name.clone(), typ.to_string(), role, n1s, n1e));
                                 break;
                             }
                         }
@@ -202,6 +330,7 @@ impl Identifiers for Clike {
                         //Push new delcaration
                         if c == '.' {
                             s = IFsm::DOT;
+                            receiver = None;
                         } else {
                             s = IFsm::NONE;
                         }
@@ -209,11 +338,38 @@ impl Identifiers for Clike {
                         if n1s > n1e || n2s > n2e {
                             continue;
                         }
-                        let name = text[n2s..n2e].to_string();
-                        let typ = text[n1s..n1e].to_string();
+                        let name = text[bytes[n2s]..bytes[n2e]].to_string();
+                        let typ = text[bytes[n1s]..bytes[n1e]].to_string();
                         if Self::is_allowed(name.as_ref()) && Self::is_allowed(typ.as_ref()) {
-                            v.push(Identifier::new(name.clone(), typ.clone(), n2s, n2e));
-                            stack.last_mut().unwrap().insert(name, typ);
+                            let top_level = stack.len() == 1;
+                            let in_parens = parens > 0 || c == ')';
+                            let frame = stack.last_mut().unwrap();
+                            let role = if in_parens && (frame.container.is_some() || top_level) {
+                                Role::Parameter
+                            } else if !in_parens && frame.container.is_some() {
+                                Role::Field
+                            } else {
+                                Role::Declaration
+                            };
+                            if role == Role::Field {
+                                if let Some(container) = &frame.container {
+                                    fields
+                                        .entry(container.clone())
+                                        .or_default()
+                                        .insert(name.clone(), typ.clone());
+                                }
+                            }
+                            if Self::is_container(&typ) {
+                                pending_container = Some(name.clone());
+                            }
+                            v.push(Identifier::new(name.clone(), typ.clone(), role, n2s, n2e));
+                            frame.names.insert(name, typ);
+                        } else if c.is_whitespace() && !Self::is_allowed(typ.as_ref()) {
+                            // Skip over modifiers like `public` so that the
+                            // next two words can still form a declaration
+                            s = IFsm::SPACE;
+                            n1s = n2s;
+                            n1e = n2e;
                         }
                     }
                 }
@@ -225,7 +381,7 @@ impl Identifiers for Clike {
 
 #[test]
 fn test_functions() {
-    let expected = "[Function { name: \"LightningOvercharge\", start: 508, end: 812 }, Function { name: \"getAction\", start: 867, end: 874 }, Function { name: \"onSpawn\", start: 947, end: 1120 }, Function { name: \"getPassiveAction\", start: 1182, end: 1233 }, Function { name: \"getCost\", start: 1274, end: 1299 }, Function { name: \"getName\", start: 1343, end: 1380 }, Function { name: \"getTip\", start: 1423, end: 1507 }, Function { name: \"getActionNetwork\", start: 1635, end: 1713 }]";
+    let expected = "[Function { name: \"LightningOvercharge\", container: Some(\"LightningOvercharge\"), kind: Function, start: 508, end: 812 }, Function { name: \"getAction\", container: Some(\"LightningOvercharge\"), kind: Function, start: 867, end: 874 }, Function { name: \"onSpawn\", container: Some(\"LightningOvercharge\"), kind: Function, start: 947, end: 1120 }, Function { name: \"getPassiveAction\", container: Some(\"LightningOvercharge\"), kind: Function, start: 1182, end: 1233 }, Function { name: \"getCost\", container: Some(\"LightningOvercharge\"), kind: Function, start: 1274, end: 1299 }, Function { name: \"getName\", container: Some(\"LightningOvercharge\"), kind: Function, start: 1343, end: 1380 }, Function { name: \"getTip\", container: Some(\"LightningOvercharge\"), kind: Function, start: 1423, end: 1507 }, Function { name: \"getActionNetwork\", container: Some(\"LightningOvercharge\"), kind: Function, start: 1635, end: 1713 }, Function { name: \"LightningOvercharge\", container: None, kind: Container, start: 452, end: 1715 }]";
     let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     let clike = Clike {};
     d.push("resources/test/functions.java");
@@ -236,7 +392,10 @@ fn test_functions() {
 
 #[test]
 fn test_identifiers() {
-    let expected = "[Identifier { name: \"com\", type_name: \"static\", start: 67, end: 70 }, Identifier { name: \"com\", type_name: \"static\", start: 232, end: 235 }, Identifier { name: \"com\", type_name: \"static\", start: 273, end: 276 }, Identifier { name: \"com\", type_name: \"static\", start: 316, end: 319 }, Identifier { name: \"com\", type_name: \"static\", start: 361, end: 364 }, Identifier { name: \"LightningOvercharge\", type_name: \"class\", start: 414, end: 433 }, Identifier { name: \"charge\", type_name: \"int\", start: 462, end: 468 }, Identifier { name: \"charge\", type_name: \"int\", start: 517, end: 523 }, Identifier { name: \"number\", type_name: \"double\", start: 547, end: 553 }, Identifier { name: \"me\", type_name: \"Session\", start: 601, end: 603 }, Identifier { name: \"number\", type_name: \"double\", start: 615, end: 621 }, Identifier { name: \"me\", type_name: \"Session\", start: 635, end: 637 }, Identifier { name: \"me\", type_name: \"Session\", start: 635, end: 637 }]";
+    // `com` is only declared by the misparse of `import static com...` as a variable, so the
+    // `com` of the later `import com...` lines is a package name rather than a use of it, and
+    // is no longer recorded as one now that the words after a modifier like `import` are parsed
+    let expected = "[Identifier { name: \"com\", type_name: \"static\", role: Declaration, owner: None, start: 67, end: 70 }, Identifier { name: \"LightningOvercharge\", type_name: \"class\", role: Declaration, owner: None, start: 414, end: 433 }, Identifier { name: \"charge\", type_name: \"int\", role: Field, owner: None, start: 462, end: 468 }, Identifier { name: \"charge\", type_name: \"int\", role: Assignment, owner: None, start: 517, end: 523 }, Identifier { name: \"number\", type_name: \"double\", role: Field, owner: None, start: 547, end: 553 }, Identifier { name: \"me\", type_name: \"Session\", role: Parameter, owner: None, start: 601, end: 603 }, Identifier { name: \"number\", type_name: \"double\", role: Assignment, owner: None, start: 615, end: 621 }, Identifier { name: \"me\", type_name: \"Session\", role: Use, owner: None, start: 635, end: 637 }, Identifier { name: \"x\", type_name: \"\", role: Assignment, owner: Some(\"Session\"), start: 638, end: 639 }]";
     let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     d.push("resources/test/identifiers.java");
     let clike = Clike {};
@@ -257,12 +416,111 @@ fn test_replace_whole() {
     let expected = std::fs::read_to_string(d).unwrap();
     assert_eq!(
         crate::nfa::replacer::replace(
-            &"".into(),
+            "",
             &text,
             crate::regexparser::parse("%s/[[type=Session]]/sess/g").unwrap(),
-            |_, _| true
+            &mut crate::nfa::replacer::accept_all
         )
         .unwrap().0,
         expected
     );
 }
+
+#[test]
+fn test_replace_role() {
+    let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    d.push("resources/test/identifiers.java");
+    let text = std::fs::read_to_string(d).unwrap();
+    let replaced = crate::nfa::replacer::replace(
+        "",
+        &text,
+        crate::regexparser::parse("%s/[[type=Session,role=param]]/sess/g").unwrap(),
+        &mut crate::nfa::replacer::accept_all,
+    )
+    .unwrap()
+    .0;
+    assert!(replaced.contains("onSpawn(Session sess)"));
+    assert!(replaced.contains("me.x = 0;"));
+    let replaced = crate::nfa::replacer::replace(
+        "",
+        &text,
+        crate::regexparser::parse("%s/[[name=number,role=decl]]/spawnFlag/g").unwrap(),
+        &mut crate::nfa::replacer::accept_all,
+    )
+    .unwrap()
+    .0;
+    assert!(replaced.contains("double spawnFlag;"));
+    assert!(replaced.contains("number = 1;"));
+}
+
+#[test]
+fn test_members() {
+    let text = "class Session {\n    int x;\n    Session next;\n    void reset(Session other) {\n        this.x = other.next.x;\n    }\n}\n";
+    let clike = Clike {};
+    let members: Vec<_> = clike
+        .read_identifiers(text)
+        .into_iter()
+        .filter(|i| i.owner.is_some())
+        .map(|i| (i.name, i.type_name, i.role))
+        .collect();
+    assert_eq!(
+        members,
+        vec![
+            ("x".to_string(), "int".to_string(), Role::Assignment),
+            ("next".to_string(), "Session".to_string(), Role::Use),
+            ("x".to_string(), "int".to_string(), Role::Use),
+        ]
+    );
+    let replaced = crate::nfa::replacer::replace(
+        "",
+        text,
+        crate::regexparser::parse("%s/[[member=x,owner=Session]]/y/g").unwrap(),
+        &mut crate::nfa::replacer::accept_all,
+    )
+    .unwrap()
+    .0;
+    assert!(replaced.contains("this.y = other.next.y;"));
+    assert!(replaced.contains("int x;"));
+    let replaced = crate::nfa::replacer::replace(
+        "",
+        text,
+        crate::regexparser::parse("%s/[[name=x,type=int]]/y/g").unwrap(),
+        &mut crate::nfa::replacer::accept_all,
+    )
+    .unwrap()
+    .0;
+    assert!(replaced.contains("this.x = other.next.x;"));
+    assert!(replaced.contains("int y;"));
+}
+
+/// Applies a query to every match within some text, for the tests of function locations
+#[cfg(test)]
+fn replace_all(text: &str, query: &str) -> String {
+    crate::nfa::replacer::replace("", text, crate::regexparser::parse(query).unwrap(), &mut crate::nfa::replacer::accept_all)
+        .unwrap()
+        .0
+}
+
+#[test]
+fn test_scoped_functions() {
+    let text = "class A {\n    void run() { x = 1; }\n    void run(int y) { x = 2; }\n    class B {\n        void run() { x = 3; }\n    }\n}\nvoid run() { x = 4; }\n".to_string();
+    let clike = Clike {};
+    let names: Vec<_> = clike.read_functions(&text).iter().map(|f| f.qualified_name()).collect();
+    assert_eq!(names, vec!["A.run", "A.run", "A.B.run", "A.B", "A", "run"]);
+    let run = |query: &str| replace_all(&text, query);
+    assert_eq!(run("{A.run}s/x/z/g").matches('z').count(), 2);
+    assert_eq!(run("{B.run}s/x/z/g").matches('z').count(), 1);
+    assert_eq!(run("{*.run}s/x/z/g").matches('z').count(), 3);
+    assert_eq!(run("{run}s/x/z/g").matches('z').count(), 4);
+    assert_eq!(run("{A}s/x/z/g").matches('z').count(), 3);
+}
+
+#[test]
+fn test_function_patterns() {
+    let text = "class A {\n    void testOne() { x = 1; }\n    void testTwo() { x = 2; }\n    void handleClick() { x = 3; }\n    void handler() { x = 4; }\n}\n";
+    let run = |query: &str| replace_all(text, query);
+    assert_eq!(run("{test*}s/x/z/g").matches('z').count(), 2);
+    assert_eq!(run("{A.*One}s/x/z/g").matches('z').count(), 1);
+    assert_eq!(run("{/^handle[A-Z]/}s/x/z/g").matches('z').count(), 1);
+    assert_eq!(run("{/^handle/}s/x/z/g").matches('z').count(), 2);
+}
@@ -32,24 +32,88 @@ pub mod parsing {
         }
     }
 
+    /// Whether a Function is a callable function, or a container such as a class
+    /// or struct whose body holds other functions
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum FunctionKind {
+        Function,
+        Container,
+    }
+
     /// Represents a function in a source code file
     #[derive(Serialize, Deserialize, Debug)]
     pub struct Function {
         /// We only care about named functions - thus, all functions have names
         pub name: String,
+        /// The qualified name of the container this function is declared within, like `Outer.Inner`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub container: Option<String>,
+        /// Whether this is a function or a container of functions
+        pub kind: FunctionKind,
         /// The start position of the function
         pub start: usize,
         /// The end position of the function
         pub end: usize,
     }
 
+    /// The part an occurrence of an identifier plays in the code around it
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Role {
+        /// A local declaration, such as `int x` within a function body
+        Declaration,
+        /// A read of a previously declared identifier
+        Use,
+        /// A declaration within the parameter list of a function
+        Parameter,
+        /// A declaration directly within the body of a class or struct
+        Field,
+        /// A use of an identifier as the target of an assignment
+        Assignment,
+    }
+
+    impl Role {
+        /// The values a `role=` query may take
+        pub const QUERIES: &'static [&'static str] =
+            &["decl", "declaration", "use", "param", "parameter", "field", "assign", "assignment"];
+
+        /// Checks whether this role is described by the value of a `role=` query
+        /// # Arguments
+        ///
+        /// * `query` - The value of the query, such as `decl` or `param`
+        ///
+        /// # Returns
+        ///
+        /// Whether the role matches, where `decl` matches any kind of declaration
+        pub fn matches(&self, query: &str) -> bool {
+            match query {
+                "decl" | "declaration" => {
+                    matches!(self, Role::Declaration | Role::Parameter | Role::Field)
+                }
+                "use" => *self == Role::Use,
+                "param" | "parameter" => *self == Role::Parameter,
+                "field" => *self == Role::Field,
+                "assign" | "assignment" => *self == Role::Assignment,
+                _ => false,
+            }
+        }
+    }
+
     /// Represents an identifier in a piece of code, which has an associated type
     #[derive(Serialize, Deserialize, Debug)]
     pub struct Identifier {
         /// The name of the identifier, which is the set of characters used to refer to it
         pub name: String,
-        /// The type of value the identifier represents
+        /// The type of value the identifier represents, which is empty for a member
+        /// access whose declaration was not seen, such as a field of a type from another file
+        #[serde(default, skip_serializing_if = "String::is_empty")]
         pub type_name: String,
+        /// Whether this occurrence declares, uses, or assigns to the identifier
+        pub role: Role,
+        /// For member accesses like `me.x`, the type of the receiver `me`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub owner: Option<String>,
         /// The starting index within the source file this identifier is located at
         pub start: usize,
         /// The index one past the end of the identifier's location
@@ -86,17 +150,46 @@ pub mod parsing {
         ///
         /// * `text` - A String that contains the name of the identifier
         /// * `typ` - A String that contains the name of the type of the identifier
+        /// * `role` - The role this occurrence of the identifier plays
         /// * `start` - a number representing the where the identifier starts in the code
         /// * `end` - a number representing the where the identifier ends in the code
         ///
-        pub fn new(name: String, typ: String, start: usize, end: usize) -> Self {
+        pub fn new(name: String, typ: String, role: Role, start: usize, end: usize) -> Self {
             Self {
                 name,
                 type_name: typ,
+                role,
+                owner: None,
                 start,
                 end,
             }
         }
+
+        /// Marks this Identifier as a member accessed on a receiver
+        /// # Arguments
+        ///
+        /// * `owner` - A String that contains the name of the type of the receiver
+        ///
+        pub fn with_owner(mut self, owner: String) -> Self {
+            self.owner = Some(owner);
+            self
+        }
+    }
+
+    /// Checks whether a name matches a pattern in which `*` matches any
+    /// sequence of characters, such as `test*` or `*Handler`
+    fn wildcard_match(pattern: &str, name: &str) -> bool {
+        match pattern.split_once('*') {
+            None => pattern == name,
+            Some((prefix, rest)) => match name.strip_prefix(prefix) {
+                Some(tail) => tail
+                    .char_indices()
+                    .map(|(i, _)| i)
+                    .chain(std::iter::once(tail.len()))
+                    .any(|i| wildcard_match(rest, &tail[i..])),
+                None => false,
+            },
+        }
     }
 
     impl Function {
@@ -106,7 +199,61 @@ pub mod parsing {
         /// * `text` - A String that contains the name of the function
         ///
         pub fn new(name: String, start: usize, end: usize) -> Self {
-            Self { name, start, end }
+            Self {
+                name,
+                container: None,
+                kind: FunctionKind::Function,
+                start,
+                end,
+            }
+        }
+
+        /// Creates a new container, such as a class, given a set of parameters
+        /// # Arguments
+        ///
+        /// * `text` - A String that contains the name of the container
+        ///
+        pub fn new_container(name: String, start: usize, end: usize) -> Self {
+            Self {
+                kind: FunctionKind::Container,
+                ..Self::new(name, start, end)
+            }
+        }
+
+        /// Sets the qualified name of the container this Function is declared within
+        /// # Arguments
+        ///
+        /// * `container` - The qualified name of the container, if there is one
+        ///
+        pub fn within(mut self, container: Option<String>) -> Self {
+            self.container = container;
+            self
+        }
+
+        /// Gets the name of this Function qualified by its containers, like `Outer.method`
+        pub fn qualified_name(&self) -> String {
+            match &self.container {
+                Some(container) => format!("{}.{}", container, self.name),
+                None => self.name.clone(),
+            }
+        }
+
+        /// Checks whether this Function is named by a pattern like `Outer.method`,
+        /// where each segment must match the end of the qualified name, and
+        /// a `*` within a segment matches any sequence of characters
+        /// # Arguments
+        ///
+        /// * `pattern` - The segments of the pattern
+        ///
+        pub fn matches(&self, pattern: &[String]) -> bool {
+            let qualified = self.qualified_name();
+            let names: Vec<&str> = qualified.split('.').collect();
+            names.len() >= pattern.len()
+                && pattern
+                    .iter()
+                    .rev()
+                    .zip(names.iter().rev())
+                    .all(|(p, n)| wildcard_match(p, n))
         }
     }
 }
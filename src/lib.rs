@@ -0,0 +1,162 @@
+//! spidior handles sed-like substitution tasks where pesky source code semantics are getting in the way.
+//!
+//! The functions at the root of the crate cover the common uses, and are what the `spidior`
+//! binary is built on:
+//!
+//! ```
+//! let command = spidior::parse_command("%s/foo/bar/g").unwrap();
+//! let path = "a.c";
+//! let source = "int foo = 1;\nfoo++;\n";
+//!
+//! let mut engine = spidior::build_engine(source);
+//! assert_eq!(spidior::find_matches(&mut engine, path, source, &command).unwrap().len(), 2);
+//!
+//! let (edited, report) =
+//!     spidior::apply_edits(path, source, &command, &mut spidior::nfa::replacer::accept_all).unwrap();
+//! assert_eq!(edited, "int bar = 1;\nbar++;\n");
+//! assert_eq!(report.replacements, 2);
+//! ```
+//!
+//! Failures are reported as an [`Error`], which for a malformed query gives the column
+//! within the query of the problem:
+//!
+//! ```
+//! let err = spidior::parse_command("%s/a|(/b/").unwrap_err();
+//! assert_eq!(err.column(), Some(6));
+//! ```
+//!
+//! The modules beneath expose the pieces they are made from, for tools that need more control.
+
+#[macro_use]
+extern crate lalrpop_util;
+
+pub mod editing;
+pub mod error;
+pub mod languages;
+pub mod nfa;
+pub mod regex2nfa;
+pub mod regexparser;
+
+pub use error::Error;
+
+use languages::{
+    clike::Clike,
+    parsing::{Functions, Identifiers, Info},
+};
+use nfa::{
+    matcher::Match,
+    queryengine::QueryEngine,
+    replacer::{Acceptor, Report},
+};
+use regexparser::ast::{Command, Replace};
+
+/// Parses a query like `%s/foo/bar/g` into a command
+///
+/// # Arguments
+///
+/// * `text` - A string slice that contains the query
+///
+/// # Returns
+///
+/// A Result<Replace, Error>, where on success, it returns the parsed command
+pub fn parse_command(text: &str) -> Result<Replace, Error> {
+    regexparser::parse(text)
+}
+
+/// Builds the engine that answers the identifier and function queries of a
+/// command, such as `[[type=int]]` and `{main}`, for a piece of source code
+///
+/// # Arguments
+///
+/// * `source` - The source code to be analyzed
+///
+/// # Returns
+///
+/// A QueryEngine for the source code
+pub fn build_engine(source: &str) -> QueryEngine {
+    QueryEngine::build(source, Box::new(Clike {}), Box::new(Clike {}))
+}
+
+/// Finds every match of the regex of a command within the location of the command
+///
+/// # Arguments
+///
+/// * `engine` - The QueryEngine built from `source`
+/// * `path_name` - The path of the file, for matching path locations against
+/// * `source` - The contents of the file
+/// * `command` - The command whose regex and location to use
+///
+/// # Returns
+///
+/// A Result<Vec<Match>, Error>, where on success, it returns the matches in order,
+/// and which will Err for a line command like `d` or `p`, or a `y` command,
+/// since they have no regex to match
+pub fn find_matches(
+    engine: &mut QueryEngine,
+    path_name: &str,
+    source: &str,
+    command: &Replace,
+) -> Result<Vec<Match>, Error> {
+    if command.command.is_line() || matches!(command.command, Command::Transliterate(_)) {
+        return Err(Error::Edit(
+            "Only substitutions and searches have a regex to find matches of".to_string(),
+        ));
+    }
+    let location = command.location.resolve_path(path_name);
+    Ok(nfa::matcher::find(engine, source, command.find.clone())?
+        .into_iter()
+        .filter(|m| location.check(source, m.start(), path_name, engine))
+        .collect())
+}
+
+/// Applies a command to the contents of a file
+///
+/// # Arguments
+///
+/// * `path_name` - The path of the file, for matching path locations against
+/// * `source` - The contents of the file
+/// * `command` - The command to apply
+/// * `acceptor` - Decides what to do with each replacement, given its Proposal
+///
+/// # Returns
+///
+/// A Result<(String, Report), Error>, where on success, it returns the
+/// new contents of the file along with a tally of the matches and replacements,
+/// and which will Err for a search command like `%/foo/` or `%p`, since it has nothing to edit
+pub fn apply_edits(
+    path_name: &str,
+    source: &str,
+    command: &Replace,
+    acceptor: &mut Acceptor,
+) -> Result<(String, Report), Error> {
+    nfa::replacer::replace(path_name, source, command.clone(), acceptor)
+}
+
+/// Gathers the functions and typed identifiers within a file, as printed by `--dump`
+///
+/// # Arguments
+///
+/// * `path_name` - The path of the file
+/// * `source` - The contents of the file
+///
+/// # Returns
+///
+/// The Info of the file
+pub fn dump_info(path_name: &str, source: &str) -> Info {
+    let c = Clike {};
+    Info::new(path_name.to_string(), c.read_functions(source), c.read_identifiers(source))
+}
+
+#[test]
+fn test_rejected_commands() -> Result<(), Error> {
+    let (path, source) = ("a.c", "int foo;\nfoo++;\n");
+    let mut engine = build_engine(source);
+    for query in ["%d", "%p", "%i\\x", "%a\\x", "%y/f/g/"] {
+        assert!(find_matches(&mut engine, path, source, &parse_command(query)?).is_err(), "{}", query);
+    }
+    assert_eq!(find_matches(&mut engine, path, source, &parse_command("%/foo/")?)?.len(), 2);
+    for query in ["%/foo/", "%p"] {
+        assert!(apply_edits(path, source, &parse_command(query)?, &mut nfa::replacer::accept_all).is_err());
+    }
+    Ok(())
+}
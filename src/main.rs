@@ -1,40 +1,53 @@
 use clap::Parser;
-use languages::clike::Clike;
-use languages::parsing::*;
-use regexparser::ast;
-use std::{error::Error, fs};
-use walkdir::WalkDir;
-use std::io::{self, BufRead};
+use ignore::{overrides::OverrideBuilder, WalkBuilder};
+use spidior::{editing, nfa, regexparser, regexparser::ast};
+use std::{error::Error, fs, path::Path};
+use std::io::{self, BufRead, Read};
+use rayon::prelude::*;
 
-#[macro_use]
-extern crate lalrpop_util;
+mod prompt;
 
-mod editing;
-mod languages;
-mod nfa;
-mod regex2nfa;
-mod regexparser;
-
-use crate::{regex2nfa::build_nfa, nfa::nfa_to_dfa};
+use spidior::{regex2nfa::build_nfa, nfa::nfa_to_dfa, nfa::replacer::Decision};
 
 #[derive(Parser)]
 #[clap(version = "0.2.3", author = "John Westhoff <johnjwesthoff@gmail.com>")]
+#[clap(subcommand_negates_reqs = true)]
 struct Opts {
-    /// The path to the files we are reading
+    #[clap(subcommand)]
+    command: Option<Commands>,
+    /// The path to the files we are reading, or - to read from stdin and write to stdout
     #[clap(short, long, default_value = ".")]
     path: String,
-    /// The query string for find/replace for each file we find in the input, required if `dump` is not set
-    #[clap(short = 'q', long, required_unless_present("dump"))]
+    /// The path to treat input read from stdin as having, for matching locations against
+    #[clap(long, default_value = "<stdin>")]
+    stdin_filename: String,
+    /// The query string for find/replace for each file we find in the input, required if `dump` or `file` is not set
+    #[clap(short = 'q', long, required_unless_present_any(&["dump", "file"]))]
     query: Option<String>,
+    /// A script of queries to apply in order, one per line, with lines starting with # ignored
+    #[clap(short = 'f', long, value_name("SCRIPT"))]
+    file: Option<String>,
     /// Whether we are are interactively replacing things or not
     #[clap(short = 'I', long)]
     interactive: bool,
     /// Optional regex for renaming files with any matches
     #[clap(short = 'R', long)]
     rename: Option<String>,
-    /// Whether we should edit files in place or print to stdout
-    #[clap(short, long)]
-    in_place: bool,
+    /// Whether we should edit files in place or print to stdout, optionally keeping the originals with a suffix, as in -i.bak
+    #[clap(short, long, min_values(0), max_values(1), value_name("SUFFIX"))]
+    in_place: Option<Option<String>>,
+    /// Whether we should print a unified diff of each changed file rather than its contents
+    #[clap(long, conflicts_with("in-place"))]
+    diff: bool,
+    /// The number of lines of context to show around each change in a diff or interactive prompt, or each match of a search [default: 3 for diffs, 2 for prompts, 0 for searches]
+    #[clap(short = 'C', long)]
+    context: Option<usize>,
+    /// Whether we should print the matches of a search, or every match and edit of a replacement, as JSON, one object per line
+    #[clap(long)]
+    json: bool,
+    /// Whether we should just count the matches and replacements in each file without changing anything
+    #[clap(long, visible_alias("count"), conflicts_with_all(&["in-place", "diff"]))]
+    dry_run: bool,
     /// Whether we should just dump info without replacing
     #[clap(short, long)]
     dump: bool,
@@ -44,103 +57,420 @@ struct Opts {
     /// Whether we should search recursively
     #[clap(short, long)]
     recursive: bool,
+    /// Only process files matching this glob, may be given more than once
+    #[clap(long, multiple_occurrences(true))]
+    include: Vec<String>,
+    /// Skip files matching this glob, may be given more than once
+    #[clap(long, multiple_occurrences(true))]
+    exclude: Vec<String>,
+    /// Whether we should process hidden files and directories
+    #[clap(long)]
+    hidden: bool,
+    /// Whether we should process files ignored by .gitignore and .ignore files
+    #[clap(long)]
+    no_ignore: bool,
+    /// The number of files to process at once, where interactive runs always process one at a time [default: the number of CPUs]
+    #[clap(short = 'j', long)]
+    jobs: Option<usize>,
 }
 
-fn ask_rename(replace: &str, with: &str) -> bool {
-    eprintln!("Rename: '{}' as '{}'\n?", replace, with);
-    let mut answer = String::new();
-    let stdin = io::stdin();
-    stdin.lock().read_line(&mut answer).unwrap();
-    return answer.to_lowercase().starts_with("y");
+#[derive(clap::Subcommand)]
+enum Commands {
+    /// Reverts the edits and renames made by the last run, refusing if any of the files have changed since
+    Undo,
 }
 
-fn ask(replace: &str, with: &str) -> bool {
-    eprintln!("Replace:\n{}\nWith:\n{}\n?", replace, with);
+fn ask_rename(replace: &str, with: &str) -> bool {
+    eprintln!("Rename: '{}' as '{}'\n?", replace, with);
     let mut answer = String::new();
     let stdin = io::stdin();
     stdin.lock().read_line(&mut answer).unwrap();
     return answer.to_lowercase().starts_with("y");
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() {
     let opts: Opts = Opts::parse();
-    if opts.dump {
+    let result = if let Some(Commands::Undo) = opts.command {
+        undo()
+    } else if opts.dump {
         dump(opts)
     } else {
         replace(opts)
+    };
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
     }
 }
 
 fn dump(opts: Opts) -> Result<(), Box<dyn Error>> {
-    let c = Clike {};
     let mut dumps = Vec::new();
-    for entry in get_dir_iter(opts.recursive, &opts.path)
-    {
-        let path = entry.path();
-        if path.is_file() {
-            if let Ok(contents) = fs::read_to_string(path) {
-                dumps.push(Info::new(path.to_string_lossy().to_string(), c.read_functions(&contents), c.read_identifiers(&contents)));
-            }
-        }
+    for (path_name, contents) in sources(&opts, &ast::Location::All)? {
+        dumps.push(spidior::dump_info(&path_name, &contents));
     }
     println!("{}", serde_json::to_string(&dumps).unwrap());
     Ok(())
 }
 
 fn replace(opts: Opts) -> Result<(), Box<dyn Error>> {
-    let replace = regexparser::parse(&opts.query.unwrap())?;
+    let mut commands = Vec::new();
+    if let Some(query) = &opts.query {
+        commands.push(regexparser::parse(query).map_err(|x| x.render(query))?);
+    }
+    if let Some(file) = &opts.file {
+        let script = fs::read_to_string(file).map_err(|x| format!("Cannot read {}: {}", file, x))?;
+        commands.extend(regexparser::parse_script(&script).map_err(|x| {
+            let line = match &x {
+                spidior::Error::Script { line, .. } => script.lines().nth(line - 1).unwrap_or_default(),
+                _ => "",
+            };
+            format!("{}: {}", file, x.render(line.trim_start().trim_end_matches('\r')))
+        })?);
+    }
+    if opts.path == "-" && (opts.in_place.is_some() || opts.interactive || opts.rename.is_some()) {
+        return Err("Input read from stdin cannot be edited in place, renamed, or replaced interactively".into());
+    }
+    if opts.path != "-" {
+        // Globs match paths relative to where the files are found, like a .gitignore
+        for command in &mut commands {
+            *command.location = command.location.anchored(&opts.path);
+        }
+    }
+    if commands.iter().any(|c| c.command.is_search()) {
+        if commands.len() != 1 {
+            return Err("A search query cannot be combined with other queries".into());
+        }
+        return search(opts, commands.remove(0));
+    }
+    let location = commands
+        .iter()
+        .map(|c| *c.location.clone())
+        .reduce(|l, r| ast::Location::Or(Box::new(l), Box::new(r)))
+        .ok_or("The script does not contain any queries")?;
     let mut rename: Option<Vec<ast::Replace>> = None;
-    if let Some(rename_str) = opts.rename {
+    if let Some(rename_str) = &opts.rename {
         let mut filenames = vec![];
-        replace.location.get_filenames(&mut filenames);
+        location.get_filenames(&mut filenames);
+        if filenames.is_empty() {
+            return Err("Renaming needs a path or glob location, like <(.*)\\.java> or g<*.java>".into());
+        }
         rename = Some(
-            filenames.iter().map(|f| regexparser::parse_rename(f, &rename_str)).filter(|x| x.is_ok()).map(|x| x.unwrap()).collect()
+            filenames
+                .iter()
+                .map(|f| regexparser::parse_rename(f, rename_str))
+                .collect::<Result<_, _>>()?,
         );
     }
     if opts.nfa {
-        let (nfa, start, end) = build_nfa(replace.clone().find);
-        let (nfa, _, _) = nfa_to_dfa(&nfa, &start, &end);
-        eprintln!("NFA is `{}`", serde_json::to_string(&nfa).unwrap());
+        for replace in &commands {
+            let (nfa, start, end) = build_nfa(replace.clone().find)?;
+            let (nfa, _, _) = nfa_to_dfa(&nfa, &start, &end);
+            eprintln!("NFA is `{}`", serde_json::to_string(&nfa).unwrap());
+        }
     }
 
-    for entry in get_dir_iter(opts.recursive, &opts.path)
-    {
-        let path = entry.path();
-        if path.is_file() {
-            if let Ok(contents) = fs::read_to_string(path) {
-                let f_name = entry.file_name().to_string_lossy();
-                let path_name = path.to_string_lossy().to_string();
-                let (res, did_change) = nfa::replacer::replace(&path_name, &contents, replace.clone(), if opts.interactive { ask } else { |_, _| true} )?;
-                eprintln!("Parsing file {}", f_name);
-                if opts.in_place {
-                    fs::write(path, &res)?;
+    let journal = editing::journal::Journal::new(editing::journal::JOURNAL);
+    let mut total = nfa::replacer::Report::default();
+    let mut modified = Vec::new();
+    let mut finish = |path_name: String, contents: String, edited: Edited| -> Result<bool, Box<dyn Error>> {
+        let path = Path::new(&path_name);
+        let Edited { contents: res, report, records } = edited;
+        let renamed = match &rename {
+            Some(renames) if report.changed() => plan_rename(renames, &path_name, opts.interactive)?,
+            _ => None,
+        };
+        if opts.json && !records.is_empty() {
+            let mut matches = Vec::new();
+            for (query, record) in records {
+                let mut value = serde_json::to_value(record)?;
+                value["query"] = query.into();
+                matches.push(value);
+            }
+            let mut file = serde_json::json!({ "path": path_name, "changed": res != contents, "matches": matches });
+            if let Some(to) = &renamed {
+                file["renamed"] = to.clone().into();
+            }
+            println!("{}", file);
+        }
+        if opts.dry_run {
+            if report.matches > 0 && !opts.json {
+                println!("{}: {} matches, {} replacements", path_name, report.matches, report.replacements);
+            }
+            total.add(report);
+            return Ok(!report.aborted);
+        }
+        if opts.path == "-" {
+            if !opts.json {
+                if opts.diff {
+                    print!("{}", editing::diff::unified_diff(&path_name, &contents, &res, opts.context.unwrap_or(3)));
                 } else {
-                    println!("{}", res);
+                    print!("{}", res);
+                }
+            }
+            return Ok(true);
+        }
+        eprintln!("Parsing file {}", path.file_name().unwrap_or_default().to_string_lossy());
+        if let Some(suffix) = &opts.in_place {
+            if res != contents {
+                journal.record_write(&path_name, &contents, &res)?;
+                editing::files::write_atomic(path, &res, suffix.as_deref())?;
+                modified.push(path_name.clone());
+            }
+        } else if !opts.json {
+            if opts.diff {
+                // A diff leaves the files alone, so any rename is part of the diff instead
+                let context = opts.context.unwrap_or(3);
+                match &renamed {
+                    Some(to) => print!("{}", editing::diff::renamed_diff(&path_name, to, &contents, &res, context)),
+                    None => print!("{}", editing::diff::unified_diff(&path_name, &contents, &res, context)),
                 }
-                if let Some(renames) = &rename {
-                    if did_change {
-                        for rename in renames {
-                            let x = nfa::replacer::replace(&path_name, &path_name, rename.clone(), if opts.interactive { ask_rename } else { |_, _| true} )?;
-                            if x.1 {
-                                eprintln!("Renaming file '{}' to '{}'", &path_name, x.0);
-                                fs::rename(&path_name, x.0)?;
-                                break;
-                            }
-                        }
-                    }
+            } else {
+                println!("{}", res);
+            }
+        }
+        // Files are only renamed when they are edited in place
+        if let (Some(to), Some(_)) = (renamed, &opts.in_place) {
+            eprintln!("Renaming file '{}' to '{}'", &path_name, to);
+            journal.record_rename(&path_name, &to)?;
+            fs::rename(&path_name, &to)?;
+        }
+        Ok(!report.aborted)
+    };
+    if opts.interactive {
+        // The prompts have to come one at a time, so interactive runs never use more than one thread
+        let mut prompter = prompt::Prompter::new(opts.context.unwrap_or(2));
+        for (path_name, contents) in sources(&opts, &location)? {
+            let edited = edit(&commands, &path_name, &contents, &mut |p| prompter.ask(p))?;
+            if !finish(path_name, contents, edited)? {
+                break;
+            }
+        }
+    } else {
+        in_order(
+            opts.jobs,
+            sources(&opts, &location)?,
+            |path_name, contents| edit(&commands, path_name, contents, &mut nfa::replacer::accept_all),
+            |path_name, contents, edited| finish(path_name, contents, edited?),
+        )?;
+    }
+    if !modified.is_empty() {
+        eprintln!("Modified {} file(s):", modified.len());
+        for path_name in modified {
+            eprintln!("  {}", path_name);
+        }
+    }
+    if opts.dry_run {
+        if !opts.json {
+            println!("total: {} matches, {} replacements", total.matches, total.replacements);
+        }
+        if total.matches == 0 {
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+fn search(opts: Opts, search: ast::Replace) -> Result<(), Box<dyn Error>> {
+    let mut found = false;
+    in_order(
+        opts.jobs,
+        sources(&opts, &search.location)?,
+        |path_name, contents| nfa::searcher::search(path_name, contents, &search, opts.context.unwrap_or(0)),
+        |_, _, hits| {
+            for hit in hits? {
+                found = true;
+                if opts.json {
+                    println!("{}", serde_json::to_string(&hit)?);
+                } else {
+                    print!("{}", hit.to_grep());
                 }
             }
+            Ok(true)
+        },
+    )?;
+    if !found {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn undo() -> Result<(), Box<dyn Error>> {
+    for action in editing::journal::undo(editing::journal::JOURNAL)? {
+        match action {
+            editing::journal::Action::Write { path, .. } => eprintln!("Restored file '{}'", path),
+            editing::journal::Action::Rename { from, to } => {
+                eprintln!("Renamed file '{}' back to '{}'", to, from)
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Works out what a file is to be renamed to, by the first rename pattern that matches its path
+///
+/// # Arguments
+///
+/// * `renames` - The rename commands, one for each path or glob location of the run
+/// * `path_name` - The path of the file
+/// * `interactive` - Whether to ask before each rename
+///
+/// # Returns
+///
+/// A Result<Option<String>, spidior::Error>, where on success, it returns the new path,
+/// or None if the file keeps its name
+fn plan_rename(renames: &[ast::Replace], path_name: &str, interactive: bool) -> Result<Option<String>, spidior::Error> {
+    for rename in renames {
+        let (to, report) = nfa::replacer::replace(path_name, path_name, rename.clone(), &mut |p| {
+            if !interactive || ask_rename(p.matched, p.replacement) {
+                Decision::Accept
+            } else {
+                Decision::Skip
+            }
+        })?;
+        if report.changed() {
+            return Ok(Some(to));
+        }
+    }
+    Ok(None)
+}
+
+/// The outcome of applying every command of a run to a file
+struct Edited {
+    /// The new contents of the file
+    contents: String,
+    /// The tally of matches and replacements over every command
+    report: nfa::replacer::Report,
+    /// The record of each match, along with the index of the command it was a match of
+    records: Vec<(usize, nfa::replacer::Record)>,
+}
+
+/// Applies each command in turn to the contents of a file
+///
+/// # Arguments
+///
+/// * `commands` - The commands to apply
+/// * `path_name` - The path of the file
+/// * `contents` - The contents of the file
+/// * `acceptor` - Decides what to do with each replacement
+///
+/// # Returns
+///
+/// The Edited file, stopping at the command the acceptor aborted during, if any
+fn edit(
+    commands: &[ast::Replace],
+    path_name: &str,
+    contents: &str,
+    acceptor: &mut nfa::replacer::Acceptor,
+) -> Result<Edited, spidior::Error> {
+    let mut res = contents.to_string();
+    let mut report = nfa::replacer::Report::default();
+    let mut records = Vec::new();
+    for (query, replace) in commands.iter().enumerate() {
+        let mut recorded = Vec::new();
+        let (r, rep) = nfa::replacer::replace_recorded(path_name, &res, replace.clone(), acceptor, &mut recorded)?;
+        res = r;
+        report.add(rep);
+        records.extend(recorded.into_iter().map(|record| (query, record)));
+        if report.aborted {
+            break;
+        }
+    }
+    Ok(Edited { contents: res, report, records })
+}
+
+/// Runs `work` over the sources on a pool of threads, while handing each result to
+/// `handle` on this thread in the same order as the sources, so the output of a run
+/// does not depend on the number of threads
+///
+/// # Arguments
+///
+/// * `jobs` - The number of threads to use, where None uses one per CPU
+/// * `sources` - The path and contents of each file
+/// * `work` - Processes the contents of a file
+/// * `handle` - Takes the path, contents and result of each file, returning false to stop
+fn in_order<T: Send>(
+    jobs: Option<usize>,
+    sources: Sources,
+    work: impl Fn(&String, &String) -> T + Sync,
+    mut handle: impl FnMut(String, String, T) -> Result<bool, Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs.unwrap_or(0)).build()?;
+    // Only a batch of files is read ahead at a time, to bound the memory used by large trees
+    let batch_size = pool.current_num_threads() * 16;
+    let mut sources = sources.peekable();
+    while sources.peek().is_some() {
+        let batch: Vec<(String, String)> = sources.by_ref().take(batch_size).collect();
+        let results: Vec<T> = pool.install(|| batch.par_iter().map(|(path_name, contents)| work(path_name, contents)).collect());
+        for ((path_name, contents), result) in batch.into_iter().zip(results) {
+            if !handle(path_name, contents, result)? {
+                return Ok(());
+            }
         }
     }
     Ok(())
 }
 
-fn get_dir_iter(recursive: bool, path: &str) -> impl Iterator<Item=walkdir::DirEntry> {
-    let mut iter = WalkDir::new(path);
-    if !recursive {
-        iter = iter.max_depth(1);
+/// The path and contents of each file to process
+type Sources<'a> = Box<dyn Iterator<Item = (String, String)> + 'a>;
+
+/// Reads each source file under the path, or stdin if the path is -, skipping any
+/// file that the location rules out by its path alone
+///
+/// # Returns
+///
+/// An iterator over the path and contents of each source
+fn sources<'a>(
+    opts: &'a Opts,
+    location: &'a ast::Location,
+) -> Result<Sources<'a>, Box<dyn Error>> {
+    if opts.path == "-" {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents)?;
+        return Ok(Box::new(std::iter::once((opts.stdin_filename.clone(), contents))));
+    }
+    Ok(Box::new(get_dir_iter(opts)?.filter_map(move |entry| {
+        let path = entry.path();
+        let path_name = path.to_string_lossy().to_string();
+        if path.is_file() && location.check_path(&path_name) != Some(false) {
+            read_source(path).map(|contents| (path_name, contents))
+        } else {
+            None
+        }
+    })))
+}
+
+fn get_dir_iter(opts: &Opts) -> Result<impl Iterator<Item = ignore::DirEntry>, Box<dyn Error>> {
+    let mut overrides = OverrideBuilder::new(&opts.path);
+    for glob in &opts.include {
+        overrides.add(glob)?;
+    }
+    for glob in &opts.exclude {
+        overrides.add(&format!("!{}", glob))?;
+    }
+    // Backups made by -i with a suffix are skipped, so that they are not edited in turn
+    let backup = opts.in_place.clone().flatten().filter(|suffix| !suffix.is_empty());
+    let mut iter = WalkBuilder::new(&opts.path);
+    if !opts.recursive {
+        iter.max_depth(Some(1));
     }
-    iter.follow_links(true)
-        .into_iter()
-        .filter_map(|e| e.ok())
+    iter.standard_filters(!opts.no_ignore)
+        .hidden(!opts.hidden)
+        .require_git(false)
+        .overrides(overrides.build()?)
+        .filter_entry(move |e| {
+            let name = e.file_name().to_string_lossy();
+            let is_backup = backup.as_deref().is_some_and(|suffix| {
+                name.ends_with(suffix) && e.file_type().is_some_and(|t| !t.is_dir())
+            });
+            name != ".git" && name != editing::journal::JOURNAL && !is_backup
+        })
+        .follow_links(true);
+    Ok(iter.build().filter_map(|e| e.ok()))
+}
+
+/// Reads a source file, skipping anything that isn't text
+fn read_source(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .filter(|contents| !contents.contains('\0'))
 }
@@ -2,6 +2,7 @@ use std::collections::{HashSet, LinkedList};
 
 #[cfg(test)]
 use crate::languages::clike::Clike;
+use crate::error::Error;
 use crate::nfa::queryengine::QueryEngine;
 use crate::nfa::Group;
 use crate::nfa::{find_path, Context};
@@ -33,15 +34,43 @@ impl Match {
         self.len
     }
 
-    pub fn get_group(&self, i: usize, s: &String) -> String {
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn end(&self) -> usize {
+        self.start + self.len
+    }
+
+    /// Gets the number of groups in the match, counting group 0, the whole match
+    pub fn group_count(&self) -> usize {
+        self.groups.len().max(1)
+    }
+
+    /// Gets where a group starts and ends within the input, if it exists,
+    /// where groups are numbered from 1 as in backreferences and group 0 is the whole match
+    pub fn group(&self, i: usize) -> Option<(usize, usize)> {
+        if i == 0 {
+            return Some((self.start, self.end()));
+        }
+        self.groups.get(i).map(|g| (g.start, g.start + g.len))
+    }
+
+    pub fn get_group(&self, i: usize, s: &str) -> String {
         if let Some(x) = self.groups.get(i) {
-            s[x.start..x.start + x.len].to_string()
+            s.chars().skip(x.start).take(x.len).collect()
         } else {
             "".to_string()
         }
     }
 }
 
+/// Gets the byte offset of each character of some text, and of its end, for converting
+/// positions counted in characters, like those of a Match, into positions in bytes
+pub fn byte_offsets(text: &str) -> Vec<usize> {
+    text.char_indices().map(|(i, _)| i).chain(std::iter::once(text.len())).collect()
+}
+
 fn path_to_matches(path: &Vec<crate::nfa::Path>, start: usize) -> Match {
     let mut groups = Vec::new();
     let mut len = 0;
@@ -75,9 +104,19 @@ fn path_to_matches(path: &Vec<crate::nfa::Path>, start: usize) -> Match {
     Match::new(start, len, groups)
 }
 
-pub fn find(qe: &mut QueryEngine, input: &String, regex: Box<Regex>) -> Vec<Match> {
+/// Finds every match of a regex within some input, from left to right
+/// # Arguments
+///
+/// * `qe` - The QueryEngine for answering `[[...]]` queries about the input
+/// * `input` - The text to search
+/// * `regex` - The parsed regex
+///
+/// # Returns
+///
+/// A Result<Vec<Match>, Error>, which will Err if the NFA for the regex could not be built
+pub fn find(qe: &mut QueryEngine, input: &str, regex: Box<Regex>) -> Result<Vec<Match>, Error> {
     let mut v = Vec::new();
-    let o = build_nfa(regex);
+    let o = build_nfa(regex)?;
     let (nfa, start, end) = nfa_to_dfa(&o.0, &o.1, &o.2);
     let mut is = 0;
     while is <= input.len() {
@@ -96,7 +135,7 @@ pub fn find(qe: &mut QueryEngine, input: &String, regex: Box<Regex>) -> Vec<Matc
         }
     }
 
-    v
+    Ok(v)
 }
 
 #[test]
@@ -104,25 +143,25 @@ fn test_find() -> Result<(), Box<dyn std::error::Error>> {
     use crate::regexparser;
     let regex = regexparser::parse("%s/bob|joe|e*//g")?.find;
     let mut qe = QueryEngine::build(
-        &"bob dole".to_string(),
+        "bob dole",
         Box::new(Clike {}),
         Box::new(Clike {}),
     );
-    assert_eq!(find(&mut qe, &"bob dole".to_string(), regex).len(), 2); //matches bob and e
+    assert_eq!(find(&mut qe, "bob dole", regex)?.len(), 2); //matches bob and e
     let regex = regexparser::parse("%s/(bob)|(joe)|(a*)//g")?.find;
-    let mut qe = QueryEngine::build(&"bo".to_string(), Box::new(Clike {}), Box::new(Clike {}));
-    assert_eq!(find(&mut qe, &"bo".to_string(), regex).len(), 0); //no match
-    let mut qe = QueryEngine::build(&"joejoe".to_string(), Box::new(Clike {}), Box::new(Clike {}));
+    let mut qe = QueryEngine::build("bo", Box::new(Clike {}), Box::new(Clike {}));
+    assert_eq!(find(&mut qe, "bo", regex)?.len(), 0); //no match
+    let mut qe = QueryEngine::build("joejoe", Box::new(Clike {}), Box::new(Clike {}));
     let regex = regexparser::parse("%s/(bob)|(joe)|(a*)//g")?.find;
-    assert_eq!(find(&mut qe, &"joejoe".to_string(), regex).len(), 2); //two matches
+    assert_eq!(find(&mut qe, "joejoe", regex)?.len(), 2); //two matches
     let regex = regexparser::parse("%s/bob|joe|e*//g")?.find;
-    let mut qe = QueryEngine::build(&"joee".to_string(), Box::new(Clike {}), Box::new(Clike {}));
-    assert_eq!(find(&mut qe, &"joee".to_string(), regex).len(), 2); //"joe", "e"
+    let mut qe = QueryEngine::build("joee", Box::new(Clike {}), Box::new(Clike {}));
+    assert_eq!(find(&mut qe, "joee", regex)?.len(), 2); //"joe", "e"
     let regex = regexparser::parse("%s/(o*)o//g")?.find;
     let os = "ooooo";
-    let mut qe = QueryEngine::build(&"ooooo".to_string(), Box::new(Clike {}), Box::new(Clike {}));
-    let found = find(&mut qe, &os.to_string(), regex);
+    let mut qe = QueryEngine::build("ooooo", Box::new(Clike {}), Box::new(Clike {}));
+    let found = find(&mut qe, os, regex)?;
     assert_eq!(found.len(), 1); //entire string
-    assert_eq!(found.get(0).unwrap().get_group(1, &os.to_string()), "oooo");
+    assert_eq!(found.get(0).unwrap().get_group(1, os), "oooo");
     Ok(())
 }
\ No newline at end of file
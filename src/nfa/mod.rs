@@ -3,7 +3,6 @@
 
 use std::{
     collections::{HashMap, HashSet, LinkedList},
-    error::Error,
     hash::Hash,
 };
 
@@ -11,12 +10,13 @@ use std::{
 use queryengine::QueryEngine;
 use serde::{Deserialize, Serialize};
 
-use crate::{languages::clike::Clike, regex2nfa::build_nfa};
+use crate::{error::Error, languages::clike::Clike, regex2nfa::build_nfa};
 type Atom = char;
 
 pub mod matcher;
 pub mod queryengine;
 pub mod replacer;
+pub mod searcher;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 enum TransitionType {
@@ -24,7 +24,7 @@ enum TransitionType {
     Alpha(Atom),
     Range(String),
     NegativeRange(String),
-    QuerySetRange(String),
+    QuerySetRange(queryengine::Predicate),
     Open(usize),
     Close(usize),
     Any,
@@ -74,6 +74,12 @@ impl Node {
     }
 }
 
+impl Default for Node {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Represents a non-deterministic finite automaton
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Nfa {
@@ -133,7 +139,7 @@ impl Nfa {
         from: &NodePointer,
         to: &NodePointer,
         s: String,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), Error> {
         self.add_transition(from, Transition::new(TransitionType::Range(s), *to))
     }
 
@@ -141,9 +147,9 @@ impl Nfa {
         &mut self,
         from: &NodePointer,
         to: &NodePointer,
-        s: String,
-    ) -> Result<(), Box<dyn Error>> {
-        self.add_transition(from, Transition::new(TransitionType::QuerySetRange(s), *to))
+        p: queryengine::Predicate,
+    ) -> Result<(), Error> {
+        self.add_transition(from, Transition::new(TransitionType::QuerySetRange(p), *to))
     }
 
     pub fn add_transition_negativerange(
@@ -151,7 +157,7 @@ impl Nfa {
         from: &NodePointer,
         to: &NodePointer,
         s: String,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), Error> {
         self.add_transition(from, Transition::new(TransitionType::NegativeRange(s), *to))
     }
 
@@ -160,7 +166,7 @@ impl Nfa {
         from: &NodePointer,
         to: &NodePointer,
         on: Atom,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), Error> {
         self.add_transition(from, Transition::new(TransitionType::Alpha(on), *to))
     }
 
@@ -168,7 +174,7 @@ impl Nfa {
         &mut self,
         from: &NodePointer,
         to: &NodePointer,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), Error> {
         self.add_transition(from, Transition::new(TransitionType::Any, *to))
     }
 
@@ -176,7 +182,7 @@ impl Nfa {
         &mut self,
         from: &NodePointer,
         to: &NodePointer,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), Error> {
         self.add_transition(from, Transition::new(TransitionType::Epsilon, *to))
     }
 
@@ -186,7 +192,7 @@ impl Nfa {
         start_to: &NodePointer,
         end_from: &NodePointer,
         end_to: &NodePointer,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), Error> {
         self.index += 1;
         self.add_transition(
             start_from,
@@ -198,8 +204,11 @@ impl Nfa {
         )
     }
 
-    fn add_transition(&mut self, from: &NodePointer, to: Transition) -> Result<(), Box<dyn Error>> {
-        let node = self.nodes.get_mut(from.id).ok_or("Invalid source!")?;
+    fn add_transition(&mut self, from: &NodePointer, to: Transition) -> Result<(), Error> {
+        let node = self.nodes.get_mut(from.id).ok_or_else(|| Error::Regex {
+            message: "Invalid source!".into(),
+            column: 0,
+        })?;
         node.transitions.push(to);
         Ok(())
     }
@@ -277,8 +286,9 @@ pub fn nfa_to_dfa(
                             end.id,
                         );
                         let trans = Transition::new(x.clone(), to);
-                        if !dfa.nodes[new_node.id].transitions.contains(&trans) {
-                            dfa.add_transition(&new_node, trans).unwrap();
+                        let transitions = &mut dfa.nodes[new_node.id].transitions;
+                        if !transitions.contains(&trans) {
+                            transitions.push(trans);
                         }
                     }
                 }
@@ -301,7 +311,7 @@ pub enum Path {
 
 pub fn find_path(
     qe: &mut crate::nfa::queryengine::QueryEngine,
-    input: &String,
+    input: &str,
     index: usize,
     nfa: &Nfa,
     node: NodePointer,
@@ -429,14 +439,14 @@ impl Context {
         while i >= self.groups.len() {
             self.groups.push(Group { start: 0, len: 0 });
         }
-        self.groups.get_mut(i).unwrap().start = self.index;
+        self.groups[i].start = self.index;
     }
 
     fn close(&mut self, i: usize) {
         while i >= self.groups.len() {
             self.groups.push(Group { start: 0, len: 0 });
         }
-        let t = self.groups.get_mut(i).unwrap();
+        let t = &mut self.groups[i];
         t.len = self.index - t.start;
     }
 
@@ -502,15 +512,8 @@ impl Context {
     }
 }
 
-#[derive(Debug)]
-pub struct NfaModel {
-    nfa: Nfa,
-    start: NodePointer,
-    end: NodePointer,
-}
-
 #[test]
-fn test_nfa_insert() -> Result<(), Box<dyn Error>> {
+fn test_nfa_insert() -> Result<(), Error> {
     let mut nfa = Nfa::new(Vec::new());
     nfa.add_node(Node::new());
     nfa.add_node(Node::new());
@@ -520,7 +523,7 @@ fn test_nfa_insert() -> Result<(), Box<dyn Error>> {
 }
 
 #[test]
-fn test_nfa_alpha_transition() -> Result<(), Box<dyn Error>> {
+fn test_nfa_alpha_transition() -> Result<(), Error> {
     let mut nfa = Nfa::new(Vec::new());
     let a = nfa.add_node(Node::new());
     let b = nfa.add_node(Node::new());
@@ -536,7 +539,7 @@ fn test_nfa_alpha_transition() -> Result<(), Box<dyn Error>> {
 }
 
 #[test]
-fn test_nfa_epsilon_transition() -> Result<(), Box<dyn Error>> {
+fn test_nfa_epsilon_transition() -> Result<(), Error> {
     let mut nfa = Nfa::new(Vec::new());
     let a = nfa.new_node();
     let b = nfa.new_node();
@@ -1,18 +1,109 @@
 use std::collections::HashMap;
 
-use crate::languages::parsing::{Functions, Identifier, Identifiers};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{describe, Error};
+use crate::languages::parsing::{Function, Functions, Identifier, Identifiers, Role};
+use crate::regexparser::ast::{Queries, Query};
+
+/// A compiled form of the criteria within a `[[...]]` query set,
+/// built once when the NFA is constructed rather than on every lookup
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Predicate {
+    name: Option<String>,
+    type_name: Option<String>,
+    role: Option<String>,
+    member: Option<String>,
+    owner: Option<String>,
+    pos: Option<(usize, usize)>,
+}
+
+impl Predicate {
+    /// Compiles the text of a query set into a Predicate
+    /// # Arguments
+    ///
+    /// * `query` - A string slice containing the criteria, such as `name=x,type=int`
+    ///
+    /// # Returns
+    ///
+    /// A Result<Predicate, Error>, which will Err if the criteria
+    /// could not be parsed, a `pos` criteria is malformed, or a `role` criteria is unknown
+    pub fn compile(query: &str) -> Result<Self, Error> {
+        let mut c = crate::regexparser::query::QueriesParser::new()
+            .parse(query)
+            .map_err(|x| {
+                let (message, column) = describe(x);
+                Error::Query { message, column }
+            })?;
+        let mut p = Self::default();
+        loop {
+            let (x, rest) = match *c {
+                Queries::Query(x) => (x, None),
+                Queries::Queries(x, r) => (x, Some(r)),
+            };
+            if let Query::Kv(k, v) = *x {
+                match k.as_ref() {
+                    "type" => p.type_name = Some(v),
+                    "name" => p.name = Some(v),
+                    "role" if !Role::QUERIES.contains(&v.as_str()) => {
+                        return Err(Error::Query {
+                            message: format!("Unknown role {}, expected one of {}", v, Role::QUERIES.join(", ")),
+                            column: query.find("role=").map(|i| i + 5).unwrap_or(0),
+                        })
+                    }
+                    "role" => p.role = Some(v),
+                    "member" => p.member = Some(v),
+                    "owner" => p.owner = Some(v),
+                    "pos" => {
+                        let error = || Error::Query {
+                            message: "Expected a position and length like pos=10:3".into(),
+                            column: query.find("pos=").map(|i| i + 4).unwrap_or(0),
+                        };
+                        let (pos_str, len_str) = v.split_once(':').ok_or_else(error)?;
+                        p.pos = Some((
+                            pos_str.parse::<usize>().map_err(|_| error())?,
+                            len_str.parse::<usize>().map_err(|_| error())?,
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            match rest {
+                Some(r) => c = r,
+                None => return Ok(p),
+            }
+        }
+    }
+
+    fn matches(&self, ident: &Identifier) -> bool {
+        // Member accesses like `me.x` are only matched by queries that ask for members,
+        // so that queries like `[[name=x]]` keep meaning the variable `x`
+        if ident.owner.is_some() && self.member.is_none() && self.owner.is_none() {
+            return false;
+        }
+        self.name.as_ref().is_none_or(|y| *y == ident.name)
+            && self.type_name.as_ref().is_none_or(|y| *y == ident.type_name)
+            && self.role.as_ref().is_none_or(|y| ident.role.matches(y))
+            && self
+                .member
+                .as_ref()
+                .is_none_or(|y| *y == ident.name && ident.owner.is_some())
+            && self.owner.as_ref().is_none_or(|y| Some(y) == ident.owner.as_ref())
+    }
+}
+
+#[derive(Default)]
 pub struct QueryEngine {
-    idents: Vec<Identifier>,
-    function_locations: HashMap<String, (usize, usize)>,
+    idents: HashMap<usize, Vec<Identifier>>,
+    functions: Vec<Function>,
     offset: usize,
 }
 
 impl QueryEngine {
-    #[cfg(test)]
     pub fn new() -> Self {
         Self {
-            idents: vec![],
-            function_locations: HashMap::new(),
+            idents: HashMap::new(),
+            functions: Vec::new(),
             offset: 0,
         }
     }
@@ -21,89 +112,70 @@ impl QueryEngine {
         self.offset = offset;
     }
 
-    pub fn build(s: &String, i: Box<dyn Identifiers>, f: Box<dyn Functions>) -> Self {
-        let functs = f.read_functions(s);
-        let mut function_locations = HashMap::new();
-        for fun in &functs {
-            function_locations.insert(fun.name.clone(), (fun.start, fun.end));
+    pub fn build(s: &str, i: Box<dyn Identifiers>, f: Box<dyn Functions>) -> Self {
+        let mut idents: HashMap<usize, Vec<Identifier>> = HashMap::new();
+        for ident in i.read_identifiers(s) {
+            idents.entry(ident.start).or_default().push(ident);
         }
         Self {
-            idents: i.read_identifiers(s),
-            function_locations,
+            idents,
+            functions: f.read_functions(s),
             offset: 0,
         }
     }
 
-    pub fn function_location(&self, name: &String) -> Option<(usize, usize)> {
-        self.function_locations.get(name).copied()
+    /// Gets every function or container whose range contains a position
+    /// # Arguments
+    ///
+    /// * `position` - The position within the source code
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the functions, so that overloaded functions and
+    /// same-named methods in different classes can all be considered
+    pub fn functions_at(&self, position: usize) -> impl Iterator<Item = &Function> {
+        self.functions
+            .iter()
+            .filter(move |f| position >= f.start && position < f.end)
     }
 
-    pub fn query(&self, position: usize, query: &String) -> Option<usize> {
-        let mut c = crate::regexparser::query::QueriesParser::new()
-            .parse(query)
-            .unwrap();
-        let mut name = None;
-        let mut kind = None;
-        loop {
-            match *c {
-                crate::regexparser::ast::Queries::Query(x) => {
-                    match *x {
-                        crate::regexparser::ast::Query::Kv(k, v) if k == "type" => {
-                            kind = Some(v);
-                        }
-                        crate::regexparser::ast::Query::Kv(k, v) if k == "name" => {
-                            name = Some(v);
-                        }
-                        crate::regexparser::ast::Query::Kv(k, v) if k == "pos" => {
-                            let mut s = v.split(":");
-                            let (pos_str, len_str) = (s.next()?, s.next()?);
-                            let (pos, len) = (pos_str.parse::<usize>().ok()?, len_str.parse::<usize>().ok()?);
-                            if position + self.offset == pos {
-                                return Some(len);
-                            } else {
-                                return None;
-                            }
-                        }
-                        _ => {}
-                    }
-                    break;
-                }
-                crate::regexparser::ast::Queries::Queries(x, r) => {
-                    match *x {
-                        crate::regexparser::ast::Query::Kv(k, v) if k == "type" => {
-                            kind = Some(v);
-                        }
-                        crate::regexparser::ast::Query::Kv(k, v) if k == "name" => {
-                            name = Some(v);
-                        }
-                        crate::regexparser::ast::Query::Kv(k, v) if k == "pos" => {
-                            let mut s = v.split(":");
-                            let (pos_str, len_str) = (s.next()?, s.next()?);
-                            let (pos, len) = (pos_str.parse::<usize>().ok()?, len_str.parse::<usize>().ok()?);
-                            if position + self.offset == pos {
-                                return Some(len);
-                            } else {
-                                return None;
-                            }
-                        }
-                        _ => {}
-                    }
-                    c = r;
-                }
-            }
-        }
-        for ident in &self.idents {
-            if match name {
-                Some(ref y) => *y == ident.name,
-                None => true,
-            } && match kind {
-                Some(ref y) => *y == ident.type_name,
-                None => true,
-            } && position + self.offset == ident.start
-            {
-                return Some(ident.end - ident.start);
-            }
+    pub fn query(&self, position: usize, query: &Predicate) -> Option<usize> {
+        if let Some((pos, len)) = query.pos {
+            return if position + self.offset == pos {
+                Some(len)
+            } else {
+                None
+            };
         }
-        None
+        self.idents
+            .get(&(position + self.offset))?
+            .iter()
+            .find(|ident| query.matches(ident))
+            .map(|ident| ident.end - ident.start)
+    }
+}
+
+#[test]
+fn test_query() -> Result<(), Error> {
+    use crate::languages::clike::Clike;
+    let text = "int x = 0;\nx = x + 1;\n".to_string();
+    let qe = QueryEngine::build(&text, Box::new(Clike {}), Box::new(Clike {}));
+    let p = Predicate::compile("name=x,type=int")?;
+    assert_eq!(qe.query(4, &p), Some(1));
+    assert_eq!(qe.query(11, &p), Some(1));
+    assert_eq!(qe.query(12, &p), None);
+    let p = Predicate::compile("name=x,role=assign")?;
+    assert_eq!(qe.query(4, &p), None);
+    assert_eq!(qe.query(11, &p), Some(1));
+    let p = Predicate::compile("pos=2:3")?;
+    assert_eq!(qe.query(2, &p), Some(3));
+    assert_eq!(qe.query(4, &p), None);
+    assert!(Predicate::compile("pos=2").is_err());
+    match Predicate::compile("name=x,role=declration") {
+        Err(Error::Query { column, .. }) => assert_eq!(column, 12),
+        x => panic!("expected an unknown role error, got {:?}", x),
     }
+    assert_eq!(Predicate::compile("name=x,pos=2:y").unwrap_err().column(), Some(11));
+    assert!(Predicate::compile("fun").is_err());
+    Ok(())
 }
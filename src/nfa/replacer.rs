@@ -1,46 +1,397 @@
-use std::error::Error;
 
 use textbuffer::TextBuffer;
 
-use super::{matcher::find, queryengine::QueryEngine};
+use serde::Serialize;
+
+use super::{
+    matcher::{byte_offsets, find},
+    queryengine::QueryEngine,
+    searcher::line_column,
+};
 use crate::nfa::matcher::Match;
 use crate::{
     editing::textbuffer,
-    languages::clike::Clike,
-    regexparser::ast::{Replace, Replacement},
+    error::Error,
+    languages::{
+        clike::Clike,
+        parsing::{Function, FunctionKind},
+    },
+    regexparser::ast::{Command, Location, Replace, Replacement},
 };
 
-pub type Acceptor = fn(&str, &str) -> bool;
+/// A replacement that is about to be made, for an Acceptor to decide on
+pub struct Proposal<'a> {
+    /// The path of the file being edited
+    pub path: &'a str,
+    /// The original contents of the file
+    pub input: &'a str,
+    /// The byte offset the match starts at within the original contents
+    pub start: usize,
+    /// The byte offset the match ends at within the original contents
+    pub end: usize,
+    /// The line the match starts on, starting from 1
+    pub line: usize,
+    /// The column the match starts at, starting from 1
+    pub column: usize,
+    /// The text that was matched
+    pub matched: &'a str,
+    /// The text of each group captured by the match, where group 0 is the whole match
+    pub groups: Vec<&'a str>,
+    /// The innermost function the match is within, if any
+    pub function: Option<&'a Function>,
+    /// The text that would replace it
+    pub replacement: &'a str,
+}
+
+/// What an Acceptor decided to do with a proposed replacement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Make the replacement as proposed
+    Accept,
+    /// Replace the match with this text instead
+    Edit(String),
+    /// Leave the match as it is
+    Skip,
+    /// Leave this and every remaining match as they are, keeping the replacements already made
+    Abort,
+}
+
+impl Decision {
+    /// Works out the text to replace a match with
+    ///
+    /// # Arguments
+    ///
+    /// * `proposed` - The proposed replacement
+    ///
+    /// # Returns
+    ///
+    /// The text to replace the match with, or None if it should be left alone
+    fn resolve(self, proposed: &str) -> Option<String> {
+        match self {
+            Decision::Accept => Some(proposed.to_string()),
+            Decision::Edit(text) => Some(text),
+            Decision::Skip | Decision::Abort => None,
+        }
+    }
+}
+
+/// Decides what to do with each replacement, and may keep state between them,
+/// such as a count of replacements made or the answers of a user
+pub type Acceptor<'a> = dyn FnMut(&Proposal) -> Decision + 'a;
+
+/// An Acceptor that makes every replacement as proposed
+pub fn accept_all(_: &Proposal) -> Decision {
+    Decision::Accept
+}
+
+/// A tally of what happened while replacing within a file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Report {
+    /// The number of matches within the location of the replacement
+    pub matches: usize,
+    /// The number of those matches that the acceptor allowed to be replaced
+    pub replacements: usize,
+    /// Whether the acceptor aborted, leaving the remaining matches alone
+    pub aborted: bool,
+}
+
+/// A record of a single match found while replacing, for reporting what happened to it
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// The byte offset the match starts at
+    pub start: usize,
+    /// The byte offset the match ends at
+    pub end: usize,
+    /// The character offset the match starts at
+    pub char_start: usize,
+    /// The character offset the match ends at
+    pub char_end: usize,
+    /// The line of the match, starting from 1
+    pub line: usize,
+    /// The column of the match, starting from 1
+    pub column: usize,
+    /// The text that was matched
+    pub text: String,
+    /// The text of each group captured by the match, where group 0 is the whole match
+    pub groups: Vec<String>,
+    /// The text that was proposed to replace the match
+    pub replacement: String,
+    /// Whether the match was within the location of the command
+    pub in_location: bool,
+    /// Whether the acceptor allowed the match to be replaced
+    pub accepted: bool,
+}
+
+impl Record {
+    /// Creates a record of a match, given its byte offsets
+    fn new(input: &str, start: usize, end: usize, groups: &[&str], replacement: &str) -> Self {
+        let (line, column) = line_column(input, start);
+        let chars = |i: usize| input.get(..i).unwrap_or(input).chars().count();
+        Self {
+            start,
+            end,
+            char_start: chars(start),
+            char_end: chars(end),
+            line,
+            column,
+            text: input.get(start..end).unwrap_or_default().to_string(),
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            replacement: replacement.to_string(),
+            in_location: true,
+            accepted: false,
+        }
+    }
+}
+
+impl Report {
+    /// Whether any text was replaced
+    pub fn changed(&self) -> bool {
+        self.replacements > 0
+    }
+
+    /// Adds the counts from another report to this one
+    pub fn add(&mut self, other: Report) {
+        self.matches += other.matches;
+        self.replacements += other.replacements;
+        self.aborted |= other.aborted;
+    }
+}
 
 pub fn replace(
-    path_name: &String,
-    input: &String,
+    path_name: &str,
+    input: &str,
+    replacement: Replace,
+    acceptor: &mut Acceptor,
+) -> Result<(String, Report), Error> {
+    replace_recorded(path_name, input, replacement, acceptor, &mut Vec::new())
+}
+
+/// Replaces within a file like `replace`, also keeping a record of every match
+/// # Arguments
+///
+/// * `path_name` - The path of the file
+/// * `input` - The contents of the file
+/// * `replacement` - The command to apply
+/// * `acceptor` - Decides whether to make each replacement
+/// * `records` - Where to add a Record of each match, in order
+///
+/// # Returns
+///
+/// A Result<(String, Report), Error>, where on success, it returns the
+/// new contents of the file along with a tally of the matches and replacements,
+/// and which will Err for a search command, since it has nothing to edit
+pub fn replace_recorded(
+    path_name: &str,
+    input: &str,
     replacement: Replace,
-    acceptor: Acceptor,
-) -> Result<(String, bool), Box<dyn Error>> {
+    acceptor: &mut Acceptor,
+    records: &mut Vec<Record>,
+) -> Result<(String, Report), Error> {
+    let location = replacement.location.resolve_path(path_name);
+    if location.check_path(path_name) == Some(false) {
+        return Ok((input.to_string(), Report::default()));
+    }
+    if replacement.command.is_search() {
+        return Err(Error::Edit(
+            "A search query only lists what it finds, so it cannot be applied as an edit".to_string(),
+        ));
+    }
     let mut qe = QueryEngine::build(input, Box::new(Clike {}), Box::new(Clike {}));
-    let matches = find(&mut qe, &input, replacement.clone().find);
+    if replacement.command.is_line() {
+        return replace_lines(path_name, input, &replacement, &location, &mut qe, acceptor, records);
+    }
+    if let Command::Transliterate(pairs) = &replacement.command {
+        return transliterate(path_name, input, pairs, &location, &mut qe, acceptor, records);
+    }
+    let matches = find(&mut qe, &input, replacement.clone().find)?;
     let mut tb = TextBuffer::new();
     let mut offset: i32 = 0;
-    let mut changed = false;
+    let mut report = Report::default();
     tb.add(input);
+    // The matcher works in characters, while the buffer and records work in bytes
+    let offsets = byte_offsets(input);
+    let byte = |c: usize| offsets.get(c).copied().unwrap_or(input.len());
     for m in matches {
         let r = replace_to_string(&replacement.replace, &m, input);
-        let start = (m.start() as i32 + offset) as usize;
-        let to_replace = tb.get(start, m.len())?;
+        let (start, end) = (byte(m.start()), byte(m.end()));
+        let at = (start as i32 + offset) as usize;
+        let to_replace = tb.get(at, end - start)?;
+        let groups: Vec<&str> = (0..m.group_count())
+            .filter_map(|i| m.group(i))
+            .map(|(s, e)| input.get(byte(s)..byte(e)).unwrap_or_default())
+            .collect();
+        let mut record = Record::new(input, start, end, &groups, &r);
 
-        let withinfunction = replacement.location.check(&input, start, path_name, &mut qe);
+        if !location.check(&input, m.start(), path_name, &mut qe) {
+            record.in_location = false;
+            records.push(record);
+            continue;
+        }
+        report.matches += 1;
+        let proposal = Proposal {
+            path: path_name,
+            input,
+            start,
+            end,
+            line: record.line,
+            column: record.column,
+            matched: &to_replace,
+            groups,
+            function: enclosing_function(&qe, m.start()),
+            replacement: &r,
+        };
+        let decision = acceptor(&proposal);
+        report.aborted = decision == Decision::Abort;
+        if let Some(r) = decision.resolve(&r) {
+            tb.replace(at, end - start, &r)?;
+            offset += r.len() as i32 - (end - start) as i32;
+            report.replacements += 1;
+            record.accepted = true;
+        }
+        records.push(record);
+        if report.aborted {
+            break;
+        }
+    }
+    Ok((tb.consume(), report))
+}
+
+/// Deletes, inserts before, or appends after each line within the location of a line command
+fn replace_lines(
+    path_name: &str,
+    input: &str,
+    replacement: &Replace,
+    location: &Location,
+    qe: &mut QueryEngine,
+    acceptor: &mut Acceptor,
+    records: &mut Vec<Record>,
+) -> Result<(String, Report), Error> {
+    let text = replace_to_string(&replacement.replace, &Match::new(0, 0, vec![]), input);
+    let mut ret = String::new();
+    let mut report = Report::default();
+    let mut start = 0;
+    // Locations work in characters, while the line is sliced out by bytes
+    let mut chars = 0;
+    for line in input.split_inclusive('\n') {
+        let line_chars = line.chars().count();
+        let end = chars + line.trim_end_matches('\n').chars().count();
+        if !report.aborted && location.check_line(input, chars, end, path_name, qe) {
+            report.matches += 1;
+            let r = match replacement.command {
+                Command::Insert => format!("{}\n{}", text, line),
+                Command::Append if line.ends_with('\n') => format!("{}{}\n", line, text),
+                Command::Append => format!("{}\n{}", line, text),
+                Command::Delete => String::new(),
+                Command::Print | Command::Search | Command::Substitute | Command::Transliterate(_) => line.to_string(),
+            };
+            let mut record = Record::new(input, start, start + line.len(), &[], &r);
+            let proposal = Proposal {
+                path: path_name,
+                input,
+                start,
+                end: start + line.len(),
+                line: record.line,
+                column: record.column,
+                matched: line,
+                groups: vec![line],
+                function: enclosing_function(qe, chars),
+                replacement: &r,
+            };
+            let decision = acceptor(&proposal);
+            report.aborted = decision == Decision::Abort;
+            let accepted = decision.resolve(&r);
+            record.accepted = accepted.is_some();
+            records.push(record);
+            if let Some(r) = accepted {
+                ret += &r;
+                report.replacements += 1;
+                start += line.len();
+                chars += line_chars;
+                continue;
+            }
+        }
+        ret += line;
+        start += line.len();
+        chars += line_chars;
+    }
+    Ok((ret, report))
+}
 
-        if withinfunction && acceptor(&to_replace, &r) {
-            tb.replace(start, m.len(), &r)?;
-            offset += r.len() as i32 - m.len() as i32;
-            changed = true;
+/// Transliterates each character within the location of a y command, treating
+/// each run of consecutive characters to transliterate as a single match
+fn transliterate(
+    path_name: &str,
+    input: &str,
+    pairs: &[(char, char)],
+    location: &Location,
+    qe: &mut QueryEngine,
+    acceptor: &mut Acceptor,
+    records: &mut Vec<Record>,
+) -> Result<(String, Report), Error> {
+    let map = |c: char| pairs.iter().find(|(from, _)| *from == c).map(|(_, to)| *to);
+    let mut ret = String::new();
+    let mut report = Report::default();
+    // Each run holds where it starts in bytes, for slicing, and in characters, for locations
+    let mut run: Option<(usize, usize, String)> = None;
+    let chars = input.char_indices().chain(std::iter::once((input.len(), '\0')));
+    for (n, (i, c)) in chars.enumerate() {
+        let to = if i < input.len() && location.check(input, n, path_name, qe) {
+            map(c)
+        } else {
+            None
+        };
+        match (to, &mut run) {
+            (Some(to), Some((_, _, r))) => r.push(to),
+            (Some(to), None) => run = Some((i, n, to.to_string())),
+            (None, _) => {
+                if let Some((start, char_start, r)) = run.take() {
+                    report.matches += 1;
+                    let mut record = Record::new(input, start, i, &[&input[start..i]], &r);
+                    let proposal = Proposal {
+                        path: path_name,
+                        input,
+                        start,
+                        end: i,
+                        line: record.line,
+                        column: record.column,
+                        matched: &input[start..i],
+                        groups: vec![&input[start..i]],
+                        function: enclosing_function(qe, char_start),
+                        replacement: &r,
+                    };
+                    let decision = acceptor(&proposal);
+                    report.aborted = decision == Decision::Abort;
+                    match decision.resolve(&r) {
+                        Some(r) => {
+                            ret += &r;
+                            report.replacements += 1;
+                            record.accepted = true;
+                        }
+                        None => ret += &input[start..i],
+                    }
+                    records.push(record);
+                    if report.aborted {
+                        ret += &input[i..];
+                        break;
+                    }
+                }
+                if i < input.len() {
+                    ret.push(c);
+                }
+            }
         }
     }
-    Ok((tb.consume(), changed))
+    Ok((ret, report))
 }
 
-fn replace_to_string(replacement: &Replacement, m: &Match, s: &String) -> String {
+/// Finds the innermost function, rather than container, that a position is within
+fn enclosing_function(qe: &QueryEngine, position: usize) -> Option<&Function> {
+    qe.functions_at(position)
+        .filter(|f| f.kind == FunctionKind::Function)
+        .min_by_key(|f| f.end - f.start)
+}
+
+fn replace_to_string(replacement: &Replacement, m: &Match, s: &str) -> String {
     let mut ret = String::new();
     for ri in &replacement.replacements {
         match ri {
@@ -66,7 +417,7 @@ fn test_replace_to_string() -> Result<(), Box<dyn std::error::Error>> {
             crate::regexparser::ast::ReplaceItem::String("!".into()),
         ],
     };
-    assert_eq!(replace_to_string(&x, &m, &"".to_string()), "hello world!");
+    assert_eq!(replace_to_string(&x, &m, ""), "hello world!");
     Ok(())
 }
 
@@ -74,56 +425,56 @@ fn test_replace_to_string() -> Result<(), Box<dyn std::error::Error>> {
 fn test_replace() -> Result<(), Box<dyn std::error::Error>> {
     use crate::regexparser;
     let regex = regexparser::parse("%s/bill/bob/g")?;
-    assert_eq!(replace(&"".into(), &"joejoe".into(), regex, |_, _| true)?.0, "joejoe");
+    assert_eq!(replace("", "joejoe", regex, &mut accept_all)?.0, "joejoe");
 
     let regex = regexparser::parse("%s/(joe)|(bob)|(a*)/bob/g")?;
-    assert_eq!(replace(&"".into(), &"joejoe".into(), regex, |_, _| true)?.0, "bobbob");
+    assert_eq!(replace("", "joejoe", regex, &mut accept_all)?.0, "bobbob");
 
     let regex = regexparser::parse("%s/bob|joe|e*/jack/g")?;
-    assert_eq!(replace(&"".into(), &"joee".into(), regex, |_, _| true)?.0, "jackjack");
+    assert_eq!(replace("", "joee", regex, &mut accept_all)?.0, "jackjack");
 
     let regex = regexparser::parse("%s/bob|joe|e*/o/g")?;
-    assert_eq!(replace(&"".into(), &"joeejoe".into(), regex, |_, _| true)?.0, "ooo");
+    assert_eq!(replace("", "joeejoe", regex, &mut accept_all)?.0, "ooo");
 
     let regex = regexparser::parse("%s/(joe)*/bob/g")?;
-    assert_eq!(replace(&"".into(), &"joejoejoejo".into(), regex, |_, _| true)?.0, "bobjo");
+    assert_eq!(replace("", "joejoejoejo", regex, &mut accept_all)?.0, "bobjo");
 
     let regex = regexparser::parse("%s/(joe)*/bob/g")?;
-    assert_eq!(replace(&"".into(), &"eee".into(), regex, |_, _| true)?.0, "eee");
+    assert_eq!(replace("", "eee", regex, &mut accept_all)?.0, "eee");
 
     let regex = regexparser::parse("%s/jo*e/bob/g")?;
     assert_eq!(
-        replace(&"".into(), &"jejoejooeej".into(), regex, |_, _| true)?.0,
+        replace("", "jejoejooeej", regex, &mut accept_all)?.0,
         "bobbobbobej"
     );
 
     let regex = regexparser::parse("%s/jo+e/bob/g")?;
     assert_eq!(
-        replace(&"".into(), &"jejoejooeej".into(), regex, |_, _| true)?.0,
+        replace("", "jejoejooeej", regex, &mut accept_all)?.0,
         "jebobbobej"
     );
 
     let regex = regexparser::parse("%s/[a-z]*/bob/g")?;
-    assert_eq!(replace(&"".into(), &"-2607".into(), regex, |_, _| true)?.0, "-2607");
+    assert_eq!(replace("", "-2607", regex, &mut accept_all)?.0, "-2607");
 
     let regex = regexparser::parse("%s/[a-z]*/bob/g")?;
     assert_eq!(
-        replace(&"".into(), &"-2e6f0z7a".into(), regex, |_, _| true)?.0,
+        replace("", "-2e6f0z7a", regex, &mut accept_all)?.0,
         "-2bob6bob0bob7bob"
     );
 
     let regex = regexparser::parse("%s/[^a-z]*/bob/g")?;
-    assert_eq!(replace(&"".into(), &"joe".into(), regex, |_, _| true)?.0, "joe");
+    assert_eq!(replace("", "joe", regex, &mut accept_all)?.0, "joe");
 
     let regex = regexparser::parse("%s/[^a-z]*/bob/g")?;
-    assert_eq!(replace(&"".into(), &"2607".into(), regex, |_, _| true)?.0, "bob");
+    assert_eq!(replace("", "2607", regex, &mut accept_all)?.0, "bob");
     Ok(())
 }
 #[test]
 fn test_replace_backref() -> Result<(), Box<dyn std::error::Error>> {
     use crate::regexparser;
     let regex = regexparser::parse("%s/(1)/\\1\\1/g")?;
-    assert_eq!(replace(&"".into(), &"1".into(), regex, |_, _| true)?.0, "11");
+    assert_eq!(replace("", "1", regex, &mut accept_all)?.0, "11");
     Ok(())
 }
 
@@ -131,10 +482,202 @@ fn test_replace_backref() -> Result<(), Box<dyn std::error::Error>> {
 fn test_pos() -> Result<(), Box<dyn std::error::Error>> {
     use crate::regexparser;
     let regex = regexparser::parse("%s/[[pos=0:3]]/bob/g")?;
-    assert_eq!(replace(&"".into(), &"joejoe".into(), regex, |_, _| true)?.0, "bobjoe");
+    assert_eq!(replace("", "joejoe", regex, &mut accept_all)?.0, "bobjoe");
     let regex = regexparser::parse("%s/[[pos=1:3]]/bob/g")?;
-    assert_eq!(replace(&"".into(), &"joejoe".into(), regex, |_, _| true)?.0, "jboboe");
+    assert_eq!(replace("", "joejoe", regex, &mut accept_all)?.0, "jboboe");
     let regex = regexparser::parse("%s/[[pos=2:1]]joe/bob/g")?;
-    assert_eq!(replace(&"".into(), &"joejoe".into(), regex, |_, _| true)?.0, "jobob");
+    assert_eq!(replace("", "joejoe", regex, &mut accept_all)?.0, "jobob");
+    Ok(())
+}
+
+#[test]
+fn test_report() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::regexparser;
+    let regex = regexparser::parse("%s/joe/bob/g")?;
+    let (_, report) = replace("", "joejoe", regex, &mut accept_all)?;
+    assert_eq!(report, Report { matches: 2, replacements: 2, aborted: false });
+    let regex = regexparser::parse("%s/joe/bob/g")?;
+    let (res, report) = replace("", "joejoe", regex, &mut |_| Decision::Skip)?;
+    assert_eq!(res, "joejoe");
+    assert_eq!(report, Report { matches: 2, replacements: 0, aborted: false });
+    assert!(!report.changed());
+    let regex = regexparser::parse("c0-3s/joe/bob/g")?;
+    let (res, report) = replace("", "joejoe", regex, &mut accept_all)?;
+    assert_eq!(res, "bobjoe");
+    assert_eq!(report, Report { matches: 1, replacements: 1, aborted: false });
+    Ok(())
+}
+
+#[test]
+fn test_acceptor() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::regexparser;
+    let regex = regexparser::parse("%s/joe/bob/g")?;
+    let mut seen = vec![];
+    let (res, report) = replace("a.c", "joe\njoe joe", regex, &mut |p| {
+        seen.push((p.path.to_string(), p.start, p.matched.to_string()));
+        match seen.len() {
+            1 => Decision::Skip,
+            2 => Decision::Edit("jim".to_string()),
+            _ => Decision::Accept,
+        }
+    })?;
+    assert_eq!(res, "joe\njim bob");
+    assert_eq!(report, Report { matches: 3, replacements: 2, aborted: false });
+    assert_eq!(seen[1], ("a.c".to_string(), 4, "joe".to_string()));
+    assert_eq!(seen[2].1, 8);
+    Ok(())
+}
+
+#[test]
+fn test_replace_lines() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::regexparser;
+    let text = "int a;\nvoid legacyInit() {\n    a = 1;\n}\nint b;".to_string();
+    let regex = regexparser::parse("{legacyInit}d")?;
+    let (res, report) = replace("", &text, regex, &mut accept_all)?;
+    assert_eq!(res, "int a;\nint b;");
+    assert_eq!(report, Report { matches: 3, replacements: 3, aborted: false });
+    let regex = regexparser::parse("l0-1i\\// Copyright")?;
+    assert_eq!(
+        replace("", &text, regex, &mut accept_all)?.0,
+        "// Copyright\nint a;\nvoid legacyInit() {\n    a = 1;\n}\nint b;"
+    );
+    let regex = regexparser::parse("l0-1|l4-5a\\// end")?;
+    assert_eq!(
+        replace("", &text, regex, &mut accept_all)?.0,
+        "int a;\n// end\nvoid legacyInit() {\n    a = 1;\n}\nint b;\n// end"
+    );
+    let regex = regexparser::parse("%d")?;
+    assert_eq!(replace("", &text, regex, &mut |_| Decision::Skip)?.0, text);
+    assert!(replace("", &text, regexparser::parse("%p")?, &mut accept_all).is_err());
+    assert!(replace("", &text, regexparser::parse("%/int/")?, &mut accept_all).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_replace_lines_non_ascii() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::regexparser;
+    let text = "// éééééééééééééééééééé\nvoid a() {\n}\nvoid b() {\n}\n".to_string();
+    let regex = regexparser::parse("{b}d")?;
+    assert_eq!(
+        replace("", &text, regex, &mut accept_all)?.0,
+        "// éééééééééééééééééééé\nvoid a() {\n}\n"
+    );
+    let text = "é\néé abc\nabc\nabc\n".to_string();
+    let regex = regexparser::parse("l2-3s/abc/ABC/g")?;
+    assert_eq!(replace("", &text, regex, &mut accept_all)?.0, "é\néé abc\nABC\nabc\n");
+    Ok(())
+}
+
+#[test]
+fn test_transliterate() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::regexparser;
+    let text = "void a() { abc(); }\nvoid b() { abc(); }\n".to_string();
+    let regex = regexparser::parse("%y/abc/ABC/")?;
+    let (res, report) = replace("", &text, regex, &mut accept_all)?;
+    assert_eq!(res, "void A() { ABC(); }\nvoid B() { ABC(); }\n");
+    assert_eq!(report, Report { matches: 4, replacements: 4, aborted: false });
+    let regex = regexparser::parse("{b}y/abc/ABC/")?;
+    assert_eq!(
+        replace("", &text, regex, &mut accept_all)?.0,
+        "void a() { abc(); }\nvoid b() { ABC(); }\n"
+    );
+    let regex = regexparser::parse("%y/ \\n/_ /")?;
+    let mut seen = vec![];
+    let (res, _) = replace("", "a b\nc", regex, &mut |p| {
+        seen.push(p.matched.to_string());
+        Decision::Accept
+    })?;
+    assert_eq!(res, "a_b c");
+    assert_eq!(seen, vec![" ", "\n"]);
+    let text = "// éééééééééééééééééééé\nvoid a() { abc(); }\nvoid b() { abc(); }\n".to_string();
+    let regex = regexparser::parse("{b}y/abc/ABC/")?;
+    assert_eq!(
+        replace("", &text, regex, &mut accept_all)?.0,
+        "// éééééééééééééééééééé\nvoid a() { abc(); }\nvoid b() { ABC(); }\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_records() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::regexparser;
+    let text = "void a() { x1; }\nvoid b() { x2; }\n".to_string();
+    let regex = regexparser::parse("{b}s/x(1|2)/y\\1/g")?;
+    let mut records = vec![];
+    let (res, _) = replace_recorded("", &text, regex, &mut accept_all, &mut records)?;
+    assert_eq!(res, "void a() { x1; }\nvoid b() { y2; }\n");
+    assert_eq!(records.len(), 2);
+    assert!(!records[0].in_location && !records[0].accepted);
+    assert_eq!(records[0].replacement, "y1");
+    assert_eq!(
+        records[1],
+        Record {
+            start: 28,
+            end: 30,
+            char_start: 28,
+            char_end: 30,
+            line: 2,
+            column: 12,
+            text: "x2".into(),
+            groups: vec!["x2".into(), "2".into()],
+            replacement: "y2".into(),
+            in_location: true,
+            accepted: true,
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn test_decisions() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::regexparser;
+    let text = "int x1;\nvoid run() {\n    x2 = x3;\n}\n".to_string();
+    let regex = regexparser::parse("%s/x([0-9])/y\\1/g")?;
+    let mut seen = vec![];
+    let mut records = vec![];
+    let (res, report) = replace_recorded("a.c", &text, regex, &mut |p| {
+        let function = p.function.map(|f| f.name.clone());
+        seen.push((p.start, p.end, p.line, p.column, p.groups[1].to_string(), function));
+        if seen.len() < 2 {
+            Decision::Accept
+        } else {
+            Decision::Abort
+        }
+    }, &mut records)?;
+    assert_eq!(res, "int y1;\nvoid run() {\n    x2 = x3;\n}\n");
+    assert_eq!(report, Report { matches: 2, replacements: 1, aborted: true });
+    assert_eq!(seen[0], (4, 6, 1, 5, "1".to_string(), None));
+    assert_eq!(seen[1], (25, 27, 3, 5, "2".to_string(), Some("run".to_string())));
+    assert_eq!(records.len(), 2);
+    let regex = regexparser::parse("%y/ab/AB/")?;
+    let (res, report) = replace("", "a b a", regex, &mut |_| Decision::Abort)?;
+    assert_eq!(res, "a b a");
+    assert!(report.aborted);
+    let regex = regexparser::parse("%d")?;
+    let (res, report) = replace("", "a\nb\n", regex, &mut |p| {
+        if p.line == 1 {
+            Decision::Accept
+        } else {
+            Decision::Abort
+        }
+    })?;
+    assert_eq!(res, "b\n");
+    assert_eq!(report, Report { matches: 2, replacements: 1, aborted: true });
+    Ok(())
+}
+
+#[test]
+fn test_records_non_ascii() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::regexparser;
+    let text = "→ foo é foo\n".to_string();
+    let regex = regexparser::parse("%s/f(o+)/b\\1/g")?;
+    let mut records = vec![];
+    let (res, _) = replace_recorded("", &text, regex, &mut accept_all, &mut records)?;
+    assert_eq!(res, "→ boo é boo\n");
+    assert_eq!((records[0].start, records[0].end, records[0].char_start, records[0].char_end), (4, 7, 2, 5));
+    assert_eq!((records[1].start, records[1].end, records[1].char_start, records[1].char_end), (11, 14, 8, 11));
+    assert_eq!((records[1].line, records[1].column), (1, 9));
+    assert_eq!(records[1].text, "foo");
+    assert_eq!(records[1].groups, vec!["foo", "oo"]);
     Ok(())
 }
@@ -0,0 +1,178 @@
+use serde::Serialize;
+
+use super::{
+    matcher::{byte_offsets, find},
+    queryengine::QueryEngine,
+};
+use crate::{
+    error::Error,
+    languages::clike::Clike,
+    regexparser::ast::{Command, Replace},
+};
+
+/// A single match found by a search, along with where it was found
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Hit {
+    /// The path of the file the match is in
+    pub path: String,
+    /// The line of the match, starting from 1
+    pub line: usize,
+    /// The column of the match, starting from 1
+    pub column: usize,
+    /// The byte offset the match starts at
+    pub start: usize,
+    /// The byte offset the match ends at
+    pub end: usize,
+    /// The text that was matched
+    pub text: String,
+    /// The lines before the matched line, up to the requested context
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub before: Vec<String>,
+    /// The lines after the matched line, up to the requested context
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+}
+
+impl Hit {
+    /// Formats the hit like grep, as `file:line:col: text`, surrounded by its context lines
+    /// formatted as `file-line- text`
+    pub fn to_grep(&self) -> String {
+        let mut ret = String::new();
+        let first = self.line - self.before.len();
+        for (i, l) in self.before.iter().enumerate() {
+            ret += &format!("{}-{}- {}\n", self.path, first + i, l);
+        }
+        ret += &format!(
+            "{}:{}:{}: {}\n",
+            self.path,
+            self.line,
+            self.column,
+            self.text.replace('\n', "\\n")
+        );
+        for (i, l) in self.after.iter().enumerate() {
+            ret += &format!("{}-{}- {}\n", self.path, self.line + i + 1, l);
+        }
+        ret
+    }
+}
+
+/// Works out the line and column of a byte offset within some text, both starting from 1,
+/// where the column counts characters
+pub fn line_column(input: &str, position: usize) -> (usize, usize) {
+    let before = input.get(..position).unwrap_or(input);
+    (
+        before.matches('\n').count() + 1,
+        before.chars().rev().take_while(|c| *c != '\n').count() + 1,
+    )
+}
+
+/// Finds every match of a query within a file, without replacing anything
+/// # Arguments
+///
+/// * `path_name` - The path of the file
+/// * `input` - The contents of the file
+/// * `search` - The query to search for, whose replacement is ignored, or a
+///   print command, which finds every line within its location
+/// * `context` - The number of lines to include before and after each match
+///
+/// # Returns
+///
+/// A Result<Vec<Hit>, Error>, where on success, it returns every match within
+/// the location of the query
+pub fn search(path_name: &str, input: &str, search: &Replace, context: usize) -> Result<Vec<Hit>, Error> {
+    let location = search.location.resolve_path(path_name);
+    if location.check_path(path_name) == Some(false) {
+        return Ok(Vec::new());
+    }
+    let mut qe = QueryEngine::build(input, Box::new(Clike {}), Box::new(Clike {}));
+    let lines: Vec<&str> = input.lines().collect();
+    let ranges: Vec<(usize, usize)> = if search.command == Command::Print {
+        let mut start = 0;
+        // Locations work in characters, while hits are reported in bytes
+        let mut chars = 0;
+        let mut ranges = Vec::new();
+        for line in input.split_inclusive('\n') {
+            let content = line.trim_end_matches('\n');
+            let line_chars = content.chars().count();
+            if location.check_line(input, chars, chars + line_chars, path_name, &mut qe) {
+                ranges.push((start, start + content.len()));
+            }
+            start += line.len();
+            chars += line.chars().count();
+        }
+        ranges
+    } else {
+        let offsets = byte_offsets(input);
+        let byte = |c: usize| offsets.get(c).copied().unwrap_or(input.len());
+        find(&mut qe, input, search.find.clone())?
+            .into_iter()
+            .filter(|m| location.check(input, m.start(), path_name, &mut qe))
+            .map(|m| (byte(m.start()), byte(m.end())))
+            .collect()
+    };
+    Ok(ranges
+        .into_iter()
+        .map(|(start, end)| {
+            let (line, column) = line_column(input, start);
+            let line = line - 1;
+            let first = line.saturating_sub(context);
+            let last = (line + context + 1).min(lines.len());
+            Hit {
+                path: path_name.to_string(),
+                line: line + 1,
+                column,
+                start,
+                end,
+                text: input.get(start..end).unwrap_or_default().to_string(),
+                before: lines.get(first..line).unwrap_or_default().iter().map(|l| l.to_string()).collect(),
+                after: lines.get(line + 1..last).unwrap_or_default().iter().map(|l| l.to_string()).collect(),
+            }
+        })
+        .collect())
+}
+
+#[test]
+fn test_search() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::regexparser;
+    let text = "int x = 0;\nx = x + 1;\nreturn x;\n".to_string();
+    let query = regexparser::parse("%/[[name=x,role=assign]]/")?;
+    let hits = search("a.c", &text, &query, 0)?;
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].to_grep(), "a.c:2:1: x\n");
+    let query = regexparser::parse("%/x/")?;
+    let hits = search("a.c", &text, &query, 1)?;
+    assert_eq!(hits.len(), 4);
+    assert_eq!((hits[2].line, hits[2].column), (2, 5));
+    assert_eq!(
+        hits[0].to_grep(),
+        "a.c:1:5: x\na.c-2- x = x + 1;\n"
+    );
+    assert_eq!(
+        hits[3].to_grep(),
+        "a.c-2- x = x + 1;\na.c:3:8: x\n"
+    );
+    let query = regexparser::parse("l0-1/x/")?;
+    assert_eq!(search("a.c", &text, &query, 0)?.len(), 1);
+    let query = regexparser::parse("l1-3p")?;
+    let hits = search("a.c", &text, &query, 0)?;
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0].to_grep(), "a.c:2:1: x = x + 1;\n");
+    Ok(())
+}
+
+#[test]
+fn test_search_non_ascii() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::regexparser;
+    let hits = search("a.c", "→→ ab\n", &regexparser::parse("%/ab/")?, 0)?;
+    assert_eq!(hits.len(), 1);
+    assert_eq!((hits[0].start, hits[0].end, hits[0].column), (7, 9, 4));
+    assert_eq!(hits[0].text, "ab");
+    let text = "// éééééééééééééééééééé\nvoid a() {\n}\nvoid b() {\n}\n".to_string();
+    let hits = search("a.c", &text, &regexparser::parse("{a}p")?, 0)?;
+    assert_eq!(hits.iter().map(|h| h.line).collect::<Vec<_>>(), vec![2, 3]);
+    assert_eq!(hits[0].text, "void a() {");
+    let hits = search("a.c", "é\néé abc\nabc\n", &regexparser::parse("l1-2/abc/")?, 0)?;
+    assert_eq!(hits.len(), 1);
+    assert_eq!((hits[0].line, hits[0].column), (2, 4));
+    Ok(())
+}
@@ -0,0 +1,169 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use spidior::nfa::replacer::{Decision, Proposal};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+const HELP: &str = "y - make this replacement
+n - skip this replacement
+a - make this and every remaining replacement
+d - skip every remaining replacement in this file
+q - skip every remaining replacement and stop, keeping the choices made so far
+e - edit the replacement text";
+
+/// Asks the user about each replacement in turn, remembering the
+/// answers that apply to more than a single replacement
+pub struct Prompter {
+    context: usize,
+    color: bool,
+    all: bool,
+    skip: Option<String>,
+}
+
+impl Prompter {
+    /// Creates a new prompter
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The number of lines to show before and after each match
+    ///
+    /// # Returns
+    ///
+    /// A Prompter that highlights matches if stderr is a terminal
+    pub fn new(context: usize) -> Self {
+        Self {
+            context,
+            color: io::stderr().is_terminal(),
+            all: false,
+            skip: None,
+        }
+    }
+
+    /// Asks the user whether to make a replacement, for use as an Acceptor
+    ///
+    /// # Arguments
+    ///
+    /// * `proposal` - The replacement that is about to be made
+    ///
+    /// # Returns
+    ///
+    /// The Decision of the user, which is to Abort if they quit or stdin runs out
+    pub fn ask(&mut self, proposal: &Proposal) -> Decision {
+        if self.skip.as_deref() == Some(proposal.path) {
+            return Decision::Skip;
+        }
+        if self.all {
+            return Decision::Accept;
+        }
+        eprint!("{}", preview(proposal, self.context, self.color));
+        loop {
+            eprint!("Replace? [y,n,a,d,q,e,?] ");
+            let answer = match read_line() {
+                Some(answer) => answer,
+                None => return Decision::Abort,
+            };
+            match answer.trim() {
+                "y" => return Decision::Accept,
+                "n" => return Decision::Skip,
+                "a" => {
+                    self.all = true;
+                    return Decision::Accept;
+                }
+                "d" => {
+                    self.skip = Some(proposal.path.to_string());
+                    return Decision::Skip;
+                }
+                "q" => return Decision::Abort,
+                "e" => {
+                    eprint!("Replace with: ");
+                    return match read_line() {
+                        Some(x) => Decision::Edit(x.trim_end_matches(&['\r', '\n'][..]).to_string()),
+                        None => Decision::Abort,
+                    };
+                }
+                _ => eprintln!("{}", HELP),
+            }
+        }
+    }
+}
+
+fn read_line() -> Option<String> {
+    io::stderr().flush().ok()?;
+    let mut answer = String::new();
+    match io::stdin().lock().read_line(&mut answer) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(answer),
+    }
+}
+
+/// Shows a replacement as `file:line:col` and the function it is within, followed by the
+/// lines it changes, before and after the replacement, surrounded by a few lines of context
+///
+/// # Arguments
+///
+/// * `proposal` - The replacement that is about to be made
+/// * `context` - The number of lines to show before and after the changed lines
+/// * `color` - Whether to highlight the match and replacement with terminal colors
+///
+/// # Returns
+///
+/// The preview as a String
+fn preview(proposal: &Proposal, context: usize, color: bool) -> String {
+    let input = proposal.input;
+    let start = proposal.start.min(input.len());
+    let end = (start + proposal.matched.len()).min(input.len());
+    let line_start = input[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_end = input[end..].find('\n').map(|i| end + i).unwrap_or(input.len());
+    let line = proposal.line;
+    let (red, green, reset) = if color { (RED, GREEN, RESET) } else { ("", "", "") };
+
+    let mut ret = format!("{}:{}:{}", proposal.path, line, proposal.column);
+    if let Some(function) = proposal.function {
+        ret += &format!(" in {}", function.qualified_name());
+    }
+    ret += "\n";
+    let before: Vec<&str> = input[..line_start].lines().collect();
+    let first = before.len().saturating_sub(context);
+    for (i, l) in before[first..].iter().enumerate() {
+        ret += &format!("  {:>4} | {}\n", first + i + 1, l);
+    }
+    let old = format!("{}{}{}{}{}", &input[line_start..start], red, proposal.matched, reset, &input[end..line_end]);
+    let new = format!("{}{}{}{}{}", &input[line_start..start], green, proposal.replacement, reset, &input[end..line_end]);
+    for (i, l) in old.split('\n').enumerate() {
+        ret += &format!("- {:>4} | {}\n", line + i, l);
+    }
+    for (i, l) in new.split('\n').enumerate() {
+        ret += &format!("+ {:>4} | {}\n", line + i, l);
+    }
+    let after_line = line + old.matches('\n').count() + 1;
+    for (i, l) in input[line_end..].lines().skip(1).take(context).enumerate() {
+        ret += &format!("  {:>4} | {}\n", after_line + i, l);
+    }
+    ret
+}
+
+#[test]
+fn test_preview() {
+    let proposal = Proposal {
+        path: "a.c",
+        input: "int x;\nint foo = 1;\nfoo++;\nreturn foo;\n",
+        start: 11,
+        end: 14,
+        line: 2,
+        column: 5,
+        matched: "foo",
+        groups: vec!["foo"],
+        function: None,
+        replacement: "bar",
+    };
+    assert_eq!(
+        preview(&proposal, 1, false),
+        "a.c:2:5\n     1 | int x;\n-    2 | int foo = 1;\n+    2 | int bar = 1;\n     3 | foo++;\n"
+    );
+    assert_eq!(
+        preview(&proposal, 0, true),
+        "a.c:2:5\n-    2 | int \x1b[31mfoo\x1b[0m = 1;\n+    2 | int \x1b[32mbar\x1b[0m = 1;\n"
+    );
+}
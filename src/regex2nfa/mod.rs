@@ -1,45 +1,60 @@
 //! This module is for building an `nfa::Nfa` from a
 //! a `regexparser::ast::Regex`
 
-use crate::{nfa::NodePointer, regexparser::parse_set};
+use crate::{
+    error::Error,
+    nfa::{queryengine::Predicate, NodePointer},
+    regexparser::parse_set,
+};
 
 use super::nfa::Nfa;
 use super::regexparser::ast::*;
 
-pub fn build_nfa(r: Box<Regex>) -> (Nfa, NodePointer, NodePointer) {
+/// Builds an NFA that accepts what a regex matches
+/// # Arguments
+///
+/// * `r` - The parsed regex
+///
+/// # Returns
+///
+/// A Result<(Nfa, NodePointer, NodePointer), Error>, where on success, it returns
+/// the NFA along with its start and accepting nodes. It will Err if a set or
+/// `[[...]]` query within the regex is malformed, with the column of the set
+/// within the regex.
+pub fn build_nfa(r: Box<Regex>) -> Result<(Nfa, NodePointer, NodePointer), Error> {
     let mut nfa = Nfa::new(Vec::new());
-    let (s, d) = do_regex(r, &mut nfa);
-    (nfa, s, d)
+    let (s, d) = do_regex(r, &mut nfa)?;
+    Ok((nfa, s, d))
 }
 
-fn do_regex(r: Box<Regex>, nfa: &mut Nfa) -> (NodePointer, NodePointer) {
+fn do_regex(r: Box<Regex>, nfa: &mut Nfa) -> Result<(NodePointer, NodePointer), Error> {
     match *r {
         Regex::Union(r) => do_union(r, nfa),
         Regex::Simple(r) => do_simple(r, nfa),
     }
 }
 
-fn do_union(r: Box<Union>, nfa: &mut Nfa) -> (NodePointer, NodePointer) {
+fn do_union(r: Box<Union>, nfa: &mut Nfa) -> Result<(NodePointer, NodePointer), Error> {
     let Union::O(x, y) = *r;
-    let a = do_regex(x, nfa);
-    let b = do_simple(y, nfa);
+    let a = do_regex(x, nfa)?;
+    let b = do_simple(y, nfa)?;
     let s = nfa.new_node();
     let d = nfa.new_node();
-    nfa.add_transition_epsilon(&s, &a.0).unwrap();
-    nfa.add_transition_epsilon(&s, &b.0).unwrap();
-    nfa.add_transition_epsilon(&a.1, &d).unwrap();
-    nfa.add_transition_epsilon(&b.1, &d).unwrap();
-    (s, d)
+    nfa.add_transition_epsilon(&s, &a.0)?;
+    nfa.add_transition_epsilon(&s, &b.0)?;
+    nfa.add_transition_epsilon(&a.1, &d)?;
+    nfa.add_transition_epsilon(&b.1, &d)?;
+    Ok((s, d))
 }
 
-fn do_simple(r: Box<Simple>, nfa: &mut Nfa) -> (NodePointer, NodePointer) {
+fn do_simple(r: Box<Simple>, nfa: &mut Nfa) -> Result<(NodePointer, NodePointer), Error> {
     match *r {
         Simple::Concatenation(r) => do_concat(r, nfa),
         Simple::Basic(r) => do_basic(r, nfa),
     }
 }
 
-fn do_basic(r: Box<Basic>, nfa: &mut Nfa) -> (NodePointer, NodePointer) {
+fn do_basic(r: Box<Basic>, nfa: &mut Nfa) -> Result<(NodePointer, NodePointer), Error> {
     match *r {
         Basic::Star(r) => do_star(r, nfa),
         Basic::Plus(r) => do_plus(r, nfa),
@@ -47,66 +62,69 @@ fn do_basic(r: Box<Basic>, nfa: &mut Nfa) -> (NodePointer, NodePointer) {
     }
 }
 
-fn do_concat(r: Box<Concatenation>, nfa: &mut Nfa) -> (NodePointer, NodePointer) {
+fn do_concat(r: Box<Concatenation>, nfa: &mut Nfa) -> Result<(NodePointer, NodePointer), Error> {
     let Concatenation::O(x, y) = *r;
-    let (ls, ld) = do_simple(x, nfa);
-    let (rs, rd) = do_basic(y, nfa);
-    nfa.add_transition_epsilon(&ld, &rs).unwrap();
-    (ls, rd)
+    let (ls, ld) = do_simple(x, nfa)?;
+    let (rs, rd) = do_basic(y, nfa)?;
+    nfa.add_transition_epsilon(&ld, &rs)?;
+    Ok((ls, rd))
 }
 
-fn do_elem(r: Box<Elementary>, nfa: &mut Nfa) -> (NodePointer, NodePointer) {
+fn do_elem(r: Box<Elementary>, nfa: &mut Nfa) -> Result<(NodePointer, NodePointer), Error> {
     match *r {
         Elementary::Group(r) => do_group(r, nfa),
         Elementary::Any(_) => do_any(nfa),
-        Elementary::Eos(_) => unimplemented!(),
+        Elementary::Eos(r) => {
+            let Eos::O(column) = *r;
+            Err(Error::Regex { message: "Matching the end of the input with $ is not supported".into(), column })
+        }
         Elementary::Char(r) => do_char(r, nfa),
         Elementary::Set(r) => do_set(r, nfa),
         Elementary::Nothing =>  do_nothing(nfa),
     }
 }
 
-fn do_star(r: Box<Star>, nfa: &mut Nfa) -> (NodePointer, NodePointer) {
+fn do_star(r: Box<Star>, nfa: &mut Nfa) -> Result<(NodePointer, NodePointer), Error> {
     let Star::O(r) = *r;
-    let (src, dst) = do_elem(r, nfa);
-    nfa.add_transition_epsilon(&dst, &src).unwrap();
-    nfa.add_transition_epsilon(&src, &dst).unwrap();
+    let (src, dst) = do_elem(r, nfa)?;
+    nfa.add_transition_epsilon(&dst, &src)?;
+    nfa.add_transition_epsilon(&src, &dst)?;
 
-    (src, dst)
+    Ok((src, dst))
 }
 
-fn do_plus(r: Box<Plus>, nfa: &mut Nfa) -> (NodePointer, NodePointer) {
+fn do_plus(r: Box<Plus>, nfa: &mut Nfa) -> Result<(NodePointer, NodePointer), Error> {
     let Plus::O(r) = *r;
-    let (src, dst) = do_elem(r, nfa);
-    nfa.add_transition_epsilon(&dst, &src).unwrap();
-    (src, dst)
+    let (src, dst) = do_elem(r, nfa)?;
+    nfa.add_transition_epsilon(&dst, &src)?;
+    Ok((src, dst))
 }
 
-fn do_nothing(nfa: &mut Nfa) -> (NodePointer, NodePointer) {
+fn do_nothing(nfa: &mut Nfa) -> Result<(NodePointer, NodePointer), Error> {
     let src = nfa.new_node();
-    (src, src)
+    Ok((src, src))
 }
 
-fn do_any(nfa: &mut Nfa) -> (NodePointer, NodePointer) {
+fn do_any(nfa: &mut Nfa) -> Result<(NodePointer, NodePointer), Error> {
     let src = nfa.new_node();
     let dst = nfa.new_node();
-    nfa.add_transition_any(&src, &dst).unwrap();
-    (src, dst)
+    nfa.add_transition_any(&src, &dst)?;
+    Ok((src, dst))
 }
 
 
-fn do_char(r: Box<Char>, nfa: &mut Nfa) -> (NodePointer, NodePointer) {
+fn do_char(r: Box<Char>, nfa: &mut Nfa) -> Result<(NodePointer, NodePointer), Error> {
     let src = nfa.new_node();
     let dst = nfa.new_node();
     let c = match *r {
         Char::Char(c) => c,
         Char::Meta(c) => c,
     };
-    nfa.add_transition_alpha(&src, &dst, c).unwrap();
-    (src, dst)
+    nfa.add_transition_alpha(&src, &dst, c)?;
+    Ok((src, dst))
 }
 
-fn do_set(r: Box<Set>, nfa: &mut Nfa) -> (NodePointer, NodePointer) {
+fn do_set(r: Box<Set>, nfa: &mut Nfa) -> Result<(NodePointer, NodePointer), Error> {
     match *r {
         Set::Positive(r) => do_positive(r, nfa),
         Set::Negative(r) => do_negative(r, nfa),
@@ -114,33 +132,32 @@ fn do_set(r: Box<Set>, nfa: &mut Nfa) -> (NodePointer, NodePointer) {
     }
 }
 
-fn do_queryset(r: Box<QuerySet>, nfa: &mut Nfa) -> (NodePointer, NodePointer) {
-    let QuerySet::O(r) = *r;
-    let r = parse_set(get_string(r));
+fn do_queryset(r: Box<QuerySet>, nfa: &mut Nfa) -> Result<(NodePointer, NodePointer), Error> {
+    let QuerySet::O(r, column) = *r;
+    let r = parse_set(get_string(r)).map_err(|x| x.shift(column))?;
     let src = nfa.new_node();
     let dst = nfa.new_node();
-    nfa.add_transition_queryset(&src, &dst, get_string(r))
-        .unwrap();
-    (src, dst)
+    let predicate = Predicate::compile(&get_string(r)).map_err(|x| x.shift(column))?;
+    nfa.add_transition_queryset(&src, &dst, predicate)?;
+    Ok((src, dst))
 }
 
-fn do_negative(r: Box<Negative>, nfa: &mut Nfa) -> (NodePointer, NodePointer) {
-    let Negative::O(r) = *r;
-    let r = parse_set(get_string(r));
+fn do_negative(r: Box<Negative>, nfa: &mut Nfa) -> Result<(NodePointer, NodePointer), Error> {
+    let Negative::O(r, column) = *r;
+    let r = parse_set(get_string(r)).map_err(|x| x.shift(column))?;
     let src = nfa.new_node();
     let dst = nfa.new_node();
-    nfa.add_transition_negativerange(&src, &dst, get_string(r))
-        .unwrap();
-    (src, dst)
+    nfa.add_transition_negativerange(&src, &dst, get_string(r))?;
+    Ok((src, dst))
 }
 
-fn do_positive(r: Box<Positive>, nfa: &mut Nfa) -> (NodePointer, NodePointer) {
-    let Positive::O(r) = *r;
-    let r = parse_set(get_string(r));
+fn do_positive(r: Box<Positive>, nfa: &mut Nfa) -> Result<(NodePointer, NodePointer), Error> {
+    let Positive::O(r, column) = *r;
+    let r = parse_set(get_string(r)).map_err(|x| x.shift(column))?;
     let src = nfa.new_node();
     let dst = nfa.new_node();
-    nfa.add_transition_range(&src, &dst, get_string(r)).unwrap();
-    (src, dst)
+    nfa.add_transition_range(&src, &dst, get_string(r))?;
+    Ok((src, dst))
 }
 
 fn get_string(r: Box<Items>) -> String {
@@ -185,13 +202,13 @@ fn get_items(r: Box<Items>) -> Vec<Box<Item>> {
     }
 }
 
-fn do_group(r: Box<Group>, nfa: &mut Nfa) -> (NodePointer, NodePointer) {
+fn do_group(r: Box<Group>, nfa: &mut Nfa) -> Result<(NodePointer, NodePointer), Error> {
     let Group::O(r) = *r;
     let src = nfa.new_node();
     let dst = nfa.new_node();
-    let x = do_regex(r, nfa);
-    nfa.add_group(&src, &x.0, &x.1, &dst).unwrap();
-    (src, dst)
+    let x = do_regex(r, nfa)?;
+    nfa.add_group(&src, &x.0, &x.1, &dst)?;
+    Ok((src, dst))
 }
 
 #[test]
@@ -200,7 +217,7 @@ fn test_regex() -> Result<(), Box<dyn std::error::Error>> {
     use std::collections::HashSet;
 
     let regex = regexparser::parse("%s/bob|joe|e*//g")?;
-    let (nfa, start, end) = build_nfa(regex.find);
+    let (nfa, start, end) = build_nfa(regex.find)?;
     let mut ctx = Context::new(HashSet::new());
     ctx.add_epsilons(vec![start].into_iter().collect(), &nfa);
     for c in "bob".chars() {
@@ -21,8 +21,6 @@
 //!<range> 	::= 	<char> "-" <char>
 
 use crate::nfa::{queryengine::QueryEngine, matcher::find};
-
-use super::reg;
 #[derive(Debug, Clone)]
 pub enum Union {
     O(Box<Regex>, Box<Simple>),
@@ -84,7 +82,8 @@ pub enum Any {
 
 #[derive(Debug, Clone)]
 pub enum Eos {
-    O,
+    /// Where the `$` is within the regex
+    O(usize),
 }
 
 #[derive(Debug, Clone)]
@@ -102,17 +101,20 @@ pub enum Set {
 
 #[derive(Debug, Clone)]
 pub enum Positive {
-    O(Box<Items>),
+    /// The items of the set, and where they start within the regex
+    O(Box<Items>, usize),
 }
 
 #[derive(Debug, Clone)]
 pub enum Negative {
-    O(Box<Items>),
+    /// The items of the set, and where they start within the regex
+    O(Box<Items>, usize),
 }
 
 #[derive(Debug, Clone)]
 pub enum QuerySet {
-    O(Box<Items>),
+    /// The items of the set, and where they start within the regex
+    O(Box<Items>, usize),
 }
 
 #[derive(Debug, Clone)]
@@ -143,10 +145,132 @@ pub enum Query {
     Kv(String, String),
     Fun,
 }
+/// A gitignore-style glob for matching file paths, compiled once when parsed
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: String,
+    matcher: globset::GlobMatcher,
+    dir_only: bool,
+    root: Option<std::path::PathBuf>,
+}
+
+impl Glob {
+    /// Compiles a glob like `src/**/*.java`, where a pattern without a slash,
+    /// like `*.java`, matches within any directory, and a pattern ending
+    /// in a slash, like `src/main/`, matches everything within a directory
+    pub fn new(pattern: &str) -> Result<Self, globset::Error> {
+        let dir_only = pattern.ends_with('/');
+        let trimmed = pattern.trim_start_matches("./").trim_end_matches('/');
+        let full = if trimmed.contains('/') {
+            trimmed.trim_start_matches('/').to_string()
+        } else {
+            format!("**/{}", trimmed)
+        };
+        let glob = globset::GlobBuilder::new(&full)
+            .literal_separator(true)
+            .build()?;
+        Ok(Self {
+            pattern: pattern.trim_start_matches("./").to_string(),
+            matcher: glob.compile_matcher(),
+            dir_only,
+            root: None,
+        })
+    }
+
+    /// Anchors the glob to the directory files are found under, so that paths
+    /// are matched relative to it, as in a .gitignore at its root
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The directory, or file, that paths are found under
+    ///
+    /// # Returns
+    ///
+    /// The glob, matching paths relative to the root
+    pub fn anchored(mut self, root: &str) -> Self {
+        self.root = Some(std::path::PathBuf::from(root));
+        self
+    }
+
+    /// Checks whether a path, or any of the directories containing it, matches the glob
+    pub fn is_match(&self, path: &str) -> bool {
+        let path = std::path::Path::new(path);
+        let relative = match self.root.as_deref().and_then(|root| path.strip_prefix(root).ok()) {
+            // the root was the file itself, which is matched by its name
+            Some(relative) if relative.as_os_str().is_empty() => path.file_name().map(std::path::Path::new),
+            relative => relative,
+        };
+        let path = relative.unwrap_or(path);
+        path.strip_prefix(".")
+            .unwrap_or(path)
+            .ancestors()
+            .skip(if self.dir_only { 1 } else { 0 })
+            .any(|p| !p.as_os_str().is_empty() && self.matcher.is_match(p))
+    }
+
+    /// Translates the glob into the regex of a path location, for renaming files,
+    /// where each wildcard becomes a group that the rename can refer back to,
+    /// so `*.java` becomes `([^/]*)\.java`
+    ///
+    /// A leading `**/` is dropped, as a path location matches anywhere within the path,
+    /// and any other `/**/` becomes `(/|/.*/)`, so that it can match no directories at all.
+    pub fn to_regex(&self) -> String {
+        let pattern = self.pattern.trim_start_matches('/');
+        let pattern = pattern.strip_prefix("**/").unwrap_or(pattern);
+        let mut ret = String::new();
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if ret.ends_with('/') && chars.next_if_eq(&'/').is_some() {
+                        ret.pop();
+                        ret += "(/|/.*/)";
+                    } else {
+                        ret += "(.*)";
+                    }
+                }
+                '*' => ret += "([^/]*)",
+                '?' => ret += "([^/])",
+                '[' => {
+                    ret += "([";
+                    if chars.next_if(|c| *c == '!' || *c == '^').is_some() {
+                        ret.push('^');
+                    }
+                    for c in chars.by_ref() {
+                        ret.push(c);
+                        if c == ']' {
+                            break;
+                        }
+                    }
+                    ret.push(')');
+                }
+                '{' => ret.push('('),
+                '}' => ret.push(')'),
+                ',' => ret.push('|'),
+                '\\' => {
+                    if let Some(c) = chars.next() {
+                        ret.push('\\');
+                        ret.push(c);
+                    }
+                }
+                c if c.is_alphanumeric() || c == '/' || c == '_' || c == '-' => ret.push(c),
+                c => {
+                    ret.push('\\');
+                    ret.push(c);
+                }
+            }
+        }
+        ret
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Location {
-    Path(String),
-    Function(String),
+    Path(String, Box<Regex>),
+    Glob(Glob),
+    Function(Vec<String>),
+    FunctionRegex(regex::Regex),
     LineRange(usize, usize),
     CharRange(usize, usize),
     Or(Box<Location>, Box<Location>),
@@ -158,32 +282,29 @@ pub enum Location {
 impl Location {
     pub(crate) fn check(
         &self,
-        input: &String,
+        input: &str,
         start: usize,
-        path_name: &String,
+        path_name: &str,
         qe: &mut QueryEngine,
     ) -> bool {
         match self {
-            crate::regexparser::ast::Location::Function(fun) => {
-                if let Some((fstart, fend)) = qe.function_location(fun) {
-                    start >= fstart && start < fend
-                } else {
-                    false
-                }
+            crate::regexparser::ast::Location::Function(pattern) => {
+                qe.functions_at(start).any(|f| f.matches(pattern))
+            }
+            crate::regexparser::ast::Location::FunctionRegex(regex) => {
+                qe.functions_at(start).any(|f| regex.is_match(&f.name))
             }
             crate::regexparser::ast::Location::CharRange(cstart, cend) => {
                 start >= *cstart && start < *cend
             }
             crate::regexparser::ast::Location::LineRange(lstart, lend) => {
-                let line = input[1..start].matches('\n').count();
+                let line = input.chars().take(start).filter(|c| *c == '\n').count();
                 line >= *lstart && line < *lend
             }
-            crate::regexparser::ast::Location::Path(regex_str) => {
-                let regex = reg::RegexParser::new()
-                .parse(regex_str)
-                .map_err(|_| "Failed to parse regex").unwrap();
-                find(qe, path_name, regex).len() > 0
-            },
+            crate::regexparser::ast::Location::Path(_, _)
+            | crate::regexparser::ast::Location::Glob(_) => {
+                self.check_path(path_name).unwrap_or(true)
+            }
             crate::regexparser::ast::Location::Or(l, r) => l.check(input, start, path_name, qe)
                 || r.check(input, start, path_name, qe),
             crate::regexparser::ast::Location::And(l, r) => l.check(input, start, path_name, qe)
@@ -192,11 +313,135 @@ impl Location {
             _ => true,
         }
     }
-    pub(crate) fn get_filenames(&self, v: &mut Vec<String>) {
+    /// Checks whether any part of a line is within this location
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The contents of the file
+    /// * `start` - Where the line starts
+    /// * `end` - Where the line ends, not counting its newline
+    /// * `path_name` - The path of the file
+    /// * `qe` - The query engine for the file
+    ///
+    /// # Returns
+    ///
+    /// true if the location includes any character of the line, or the start of an empty line
+    pub(crate) fn check_line(
+        &self,
+        input: &str,
+        start: usize,
+        end: usize,
+        path_name: &str,
+        qe: &mut QueryEngine,
+    ) -> bool {
+        (start..end.max(start + 1)).any(|i| self.check(input, i, path_name, qe))
+    }
+    /// Evaluates the parts of this location that depend only on the path of
+    /// a file, so that files can be skipped before they are even read
+    ///
+    /// # Arguments
+    ///
+    /// * `path_name` - The path of the file
+    ///
+    /// # Returns
+    ///
+    /// Some(x) if the location is x everywhere within the file, or None
+    /// if it depends on the contents of the file
+    pub fn check_path(&self, path_name: &str) -> Option<bool> {
+        match self {
+            crate::regexparser::ast::Location::All => Some(true),
+            crate::regexparser::ast::Location::Path(_, regex) => {
+                // path regexes are checked to build when the location is parsed
+                Some(find(&mut QueryEngine::new(), path_name, regex.clone()).is_ok_and(|m| !m.is_empty()))
+            }
+            crate::regexparser::ast::Location::Glob(glob) => Some(glob.is_match(path_name)),
+            crate::regexparser::ast::Location::Or(l, r) => {
+                match (l.check_path(path_name), r.check_path(path_name)) {
+                    (Some(true), _) | (_, Some(true)) => Some(true),
+                    (Some(false), Some(false)) => Some(false),
+                    _ => None,
+                }
+            }
+            crate::regexparser::ast::Location::And(l, r) => {
+                match (l.check_path(path_name), r.check_path(path_name)) {
+                    (Some(false), _) | (_, Some(false)) => Some(false),
+                    (Some(true), Some(true)) => Some(true),
+                    _ => None,
+                }
+            }
+            crate::regexparser::ast::Location::Not(l) => l.check_path(path_name).map(|x| !x),
+            _ => None,
+        }
+    }
+    /// Resolves the parts of this location that depend only on the path of a file,
+    /// so that they are evaluated once per file rather than once per match
+    ///
+    /// # Arguments
+    ///
+    /// * `path_name` - The path of the file
+    ///
+    /// # Returns
+    ///
+    /// A Location without any Path or Glob locations within it
+    pub(crate) fn resolve_path(&self, path_name: &str) -> Location {
+        match self.check_path(path_name) {
+            Some(true) => Location::All,
+            Some(false) => Location::Not(Box::new(Location::All)),
+            None => match self {
+                crate::regexparser::ast::Location::Or(l, r) => Location::Or(
+                    Box::new(l.resolve_path(path_name)),
+                    Box::new(r.resolve_path(path_name)),
+                ),
+                crate::regexparser::ast::Location::And(l, r) => Location::And(
+                    Box::new(l.resolve_path(path_name)),
+                    Box::new(r.resolve_path(path_name)),
+                ),
+                crate::regexparser::ast::Location::Not(l) => {
+                    Location::Not(Box::new(l.resolve_path(path_name)))
+                }
+                x => x.clone(),
+            },
+        }
+    }
+
+    /// Anchors every glob location within this location to the directory files are found under,
+    /// as with [`Glob::anchored`]
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The directory, or file, that paths are found under
+    ///
+    /// # Returns
+    ///
+    /// The location, with its globs matching paths relative to the root
+    pub fn anchored(&self, root: &str) -> Location {
         match self {
-            crate::regexparser::ast::Location::Path(suffix) => {
+            crate::regexparser::ast::Location::Glob(glob) => Location::Glob(glob.clone().anchored(root)),
+            crate::regexparser::ast::Location::Or(l, r) => {
+                Location::Or(Box::new(l.anchored(root)), Box::new(r.anchored(root)))
+            }
+            crate::regexparser::ast::Location::And(l, r) => {
+                Location::And(Box::new(l.anchored(root)), Box::new(r.anchored(root)))
+            }
+            crate::regexparser::ast::Location::Not(l) => Location::Not(Box::new(l.anchored(root))),
+            x => x.clone(),
+        }
+    }
+
+    /// Collects the patterns of every path and glob location within this location, for renaming files,
+    /// with globs translated into regexes by [`Glob::to_regex`]
+    ///
+    /// # Arguments
+    ///
+    /// * `v` - Where to add the patterns
+    pub fn get_filenames(&self, v: &mut Vec<String>) {
+        match self {
+            crate::regexparser::ast::Location::Path(suffix, _) => {
                 v.push(suffix.clone());
             }
+            crate::regexparser::ast::Location::Glob(glob) => {
+                v.push(glob.to_regex());
+            }
             crate::regexparser::ast::Location::Or(l, r) => {
                 l.get_filenames(v);
                 r.get_filenames(v);
@@ -218,8 +463,41 @@ pub enum ReplaceItem {
     BackRef(usize),
 }
 
+/// What a query does with each of its matches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `LOCATIONs/REGEX/REPLACEMENT/`, replacing each match
+    Substitute,
+    /// `LOCATION/REGEX/`, just listing each match
+    Search,
+    /// `LOCATIONd`, deleting each line within the location
+    Delete,
+    /// `LOCATIONp`, just listing each line within the location
+    Print,
+    /// `LOCATIONi\TEXT`, inserting a line before each line within the location
+    Insert,
+    /// `LOCATIONa\TEXT`, appending a line after each line within the location
+    Append,
+    /// `LOCATIONy/SOURCE/DEST/`, replacing each character of SOURCE within the
+    /// location with the character in the same position of DEST
+    Transliterate(Vec<(char, char)>),
+}
+
+impl Command {
+    /// Whether the command only lists what it finds rather than editing anything
+    pub fn is_search(&self) -> bool {
+        matches!(self, Command::Search | Command::Print)
+    }
+
+    /// Whether the command works on whole lines rather than on matches of a regex
+    pub fn is_line(&self) -> bool {
+        matches!(self, Command::Delete | Command::Print | Command::Insert | Command::Append)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Replace {
+    pub command: Command,
     pub find: Box<Regex>,
     pub replace: Box<Replacement>,
     pub global: bool,
@@ -228,13 +506,69 @@ pub struct Replace {
 
 #[derive(Debug, Clone)]
 pub struct ReplaceUnparsed {
+    pub command: Command,
     pub find: String,
     pub replace: String,
     pub location: String,
     pub global: bool,
+    /// Where the find and replace portions start within the command, for reporting errors
+    pub find_start: usize,
+    pub replace_start: usize,
 }
 
 #[derive(Debug, Clone)]
 pub struct Replacement {
     pub replacements: Vec<ReplaceItem>,
 }
+
+#[test]
+fn test_glob() -> Result<(), Box<dyn std::error::Error>> {
+    let glob = Glob::new("src/**/*.java")?;
+    assert!(glob.is_match("src/main/Foo.java"));
+    assert!(glob.is_match("./src/Foo.java"));
+    assert!(!glob.is_match("test/src/Foo.java"));
+    assert!(!glob.is_match("src/Foo.rs"));
+    let glob = Glob::new("*.java")?;
+    assert!(glob.is_match("Foo.java"));
+    assert!(glob.is_match("src/main/Foo.java"));
+    let glob = Glob::new("src/main/")?;
+    assert!(glob.is_match("src/main/Foo.java"));
+    assert!(glob.is_match("src/main/deep/Foo.java"));
+    assert!(!glob.is_match("src/main"));
+    assert!(!glob.is_match("src/mainly/Foo.java"));
+    let glob = Glob::new("src/**/*.java")?.anchored("proj");
+    assert!(glob.is_match("proj/src/main/Foo.java"));
+    assert!(!glob.is_match("proj/test/src/Foo.java"));
+    let glob = Glob::new("src/main/")?.anchored("/home/me/proj/");
+    assert!(glob.is_match("/home/me/proj/src/main/Foo.java"));
+    assert!(!glob.is_match("/home/me/src/main/Foo.java"));
+    assert!(Glob::new("*.java")?.anchored("Foo.java").is_match("Foo.java"));
+    Ok(())
+}
+
+#[test]
+fn test_glob_to_regex() -> Result<(), Box<dyn std::error::Error>> {
+    assert_eq!(Glob::new("*.java")?.to_regex(), r"([^/]*)\.java");
+    assert_eq!(Glob::new("./src/**/Fo?.{c,h}")?.to_regex(), r"src(/|/.*/)Fo([^/])\.(c|h)");
+    assert_eq!(Glob::new("**/[!a-c]x.rs")?.to_regex(), r"([^a-c])x\.rs");
+    let mut filenames = vec![];
+    Location::Or(
+        Box::new(Location::Glob(Glob::new("*.java")?)),
+        Box::new(Location::Function(vec!["main".into()])),
+    )
+    .get_filenames(&mut filenames);
+    assert_eq!(filenames, vec![r"([^/]*)\.java".to_string()]);
+    Ok(())
+}
+
+#[test]
+fn test_check_path() -> Result<(), Box<dyn std::error::Error>> {
+    let location = crate::regexparser::parse("g<src/**/*.java>&{foo}s/a/b/")?.location;
+    assert_eq!(location.check_path("docs/Foo.java"), Some(false));
+    assert_eq!(location.check_path("src/Foo.java"), None);
+    let location = crate::regexparser::parse("<.rs>|g<*.java>s/a/b/")?.location;
+    assert_eq!(location.check_path("src/Foo.java"), Some(true));
+    assert_eq!(location.check_path("src/mod.rs"), Some(true));
+    assert_eq!(location.check_path("src/mod.c"), Some(false));
+    Ok(())
+}
@@ -1,14 +1,33 @@
 use self::ast::{Items, Replace};
-use std::error::Error;
+use crate::error::{describe, Error};
+use lalrpop_util::ParseError;
 
 pub mod ast;
 mod parsecommand;
 mod parsereplacement;
 
-lalrpop_mod!(reg, "/regexparser/reg.rs");
-lalrpop_mod!(set, "/regexparser/set.rs");
-lalrpop_mod!(pub query, "/regexparser/query.rs");
-lalrpop_mod!(location, "/regexparser/location.rs");
+// The generated parsers name their values like `__0`, copy their locations with clone, and spell out the
+// types of their custom errors, none of which is worth linting
+lalrpop_mod!(
+    #[allow(clippy::clone_on_copy, clippy::type_complexity, clippy::just_underscores_and_digits, clippy::needless_lifetimes)]
+    reg,
+    "/regexparser/reg.rs"
+);
+lalrpop_mod!(
+    #[allow(clippy::clone_on_copy, clippy::type_complexity, clippy::just_underscores_and_digits, clippy::needless_lifetimes)]
+    set,
+    "/regexparser/set.rs"
+);
+lalrpop_mod!(
+    #[allow(clippy::clone_on_copy, clippy::type_complexity, clippy::just_underscores_and_digits, clippy::needless_lifetimes)]
+    pub query,
+    "/regexparser/query.rs"
+);
+lalrpop_mod!(
+    #[allow(clippy::clone_on_copy, clippy::type_complexity, clippy::just_underscores_and_digits, clippy::needless_lifetimes)]
+    location,
+    "/regexparser/location.rs"
+);
 
 /// For parsing out statements of the form
 /// LOCATIONs/REGEX/REPLACEMENT/G
@@ -26,24 +45,41 @@ lalrpop_mod!(location, "/regexparser/location.rs");
 ///
 /// # Returns
 ///
-/// A Result<Replace, Box<dyn Error>>, where on success, it returns a
+/// A Result<Replace, Error>, where on success, it returns a
 /// Replace containing the LOCATION, REGEX, REPLACEMENT, and
-/// whether it is global or not (ends with a g)
-pub fn parse(text: &str) -> Result<ast::Replace, Box<dyn Error>> {
+/// whether it is global or not (ends with a g). It will Err with
+/// the column within `text` of the first problem found.
+pub fn parse(text: &str) -> Result<ast::Replace, Error> {
+    parse_bytes(text).map_err(|x| x.in_chars(text))
+}
+
+/// Parses a command like `parse`, but with the columns of any error counted in bytes
+fn parse_bytes(text: &str) -> Result<ast::Replace, Error> {
     let ru = parsecommand::parse(text)?;
-    let location = location::LocationParser::new()
-        .parse(&ru.location)
-        .map_err(|x| format!("Failed to parse location: {}", x))?;
+    let location = location::LocationParser::new().parse(&ru.location).map_err(|x| {
+        let (message, column) = describe(x);
+        Error::Location { message, column }
+    })?;
     let find = if ru.find.is_empty() {
         Box::new(ast::Regex::Simple(Box::new(ast::Simple::Basic(Box::new(ast::Basic::Elementary(Box::new(ast::Elementary::Nothing)))))))
 
     } else {
-        reg::RegexParser::new()
-        .parse(&ru.find)
-        .map_err(|x| format!("Failed to parse regex: {}", x))?
+        let find = reg::RegexParser::new().parse(&ru.find).map_err(|x| {
+            let (message, column) = describe(x);
+            Error::Regex { message, column: ru.find_start + column }
+        })?;
+        crate::regex2nfa::build_nfa(find.clone()).map_err(|x| x.shift(ru.find_start))?;
+        find
+    };
+    let replace = if ru.command.is_line() {
+        ast::Replacement {
+            replacements: vec![ast::ReplaceItem::String(ru.replace)],
+        }
+    } else {
+        parsereplacement::parse(&ru.replace).map_err(|x| x.shift(ru.replace_start))?
     };
-    let replace = parsereplacement::parse(&ru.replace)?;
     Ok(Replace {
+        command: ru.command,
         location,
         find,
         replace: Box::new(replace),
@@ -51,13 +87,43 @@ pub fn parse(text: &str) -> Result<ast::Replace, Box<dyn Error>> {
     })
 }
 
-pub fn parse_rename(fromname: &str, rename: &str) -> Result<ast::Replace, Box<dyn Error>> {
+/// For parsing a script of commands, one per line, like `sed -f`
+///
+/// Blank lines and lines starting with a # are ignored.
+///
+/// # Arguments
+///
+/// * `text` - A string slice that contains the script to be parsed
+///
+/// # Returns
+///
+/// A Result<Vec<Replace>, Error>, where on success, it returns
+/// each command in the order it appears in the script. It will Err with
+/// the line number of the first command that fails to parse.
+pub fn parse_script(text: &str) -> Result<Vec<ast::Replace>, Error> {
+    let mut commands = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_start().trim_end_matches('\r');
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        commands.push(parse(line).map_err(|x| Error::Script {
+            line: i + 1,
+            source: Box::new(x),
+        })?);
+    }
+    Ok(commands)
+}
+
+pub fn parse_rename(fromname: &str, rename: &str) -> Result<ast::Replace, Error> {
     let location = Box::new(ast::Location::All);
-    let find = reg::RegexParser::new()
-        .parse(fromname)
-        .map_err(|x| format!("Failed to parse filename regex: {}", x))?;
-    let replace = parsereplacement::parse(rename)?;
+    let find = reg::RegexParser::new().parse(fromname).map_err(|x| {
+        let (message, column) = describe(x);
+        Error::Regex { message, column }.in_chars(fromname)
+    })?;
+    let replace = parsereplacement::parse(rename).map_err(|x| x.in_chars(rename))?;
     Ok(Replace {
+        command: ast::Command::Substitute,
         location,
         find,
         replace: Box::new(replace),
@@ -65,8 +131,34 @@ pub fn parse_rename(fromname: &str, rename: &str) -> Result<ast::Replace, Box<dy
     })
 }
 
-pub fn parse_set(s: String) -> Box<Items> {
-    set::ItemsParser::new().parse(&s).unwrap()
+/// Parses the items of a set like `[a-z]`, which may hold ranges
+/// # Arguments
+///
+/// * `s` - The text between the brackets of the set
+///
+/// # Returns
+///
+/// A Result<Box<Items>, Error>, which will Err if a range is incomplete, as in `[a-]`
+pub fn parse_set(s: String) -> Result<Box<Items>, Error> {
+    set::ItemsParser::new().parse(&s).map_err(|x| {
+        let (message, column) = describe(x);
+        Error::Regex { message, column }
+    })
+}
+
+/// Parses a bound of a line or character range within a location, like the 3 of `l3-5`
+/// # Arguments
+///
+/// * `start` - Where the range starts within the location
+/// * `s` - The digits of the bound
+///
+/// # Returns
+///
+/// The bound, or an error at the start of the range if it is missing or too large
+pub(crate) fn range_bound<T>(start: usize, s: &str) -> Result<usize, ParseError<usize, T, (usize, &'static str)>> {
+    s.parse::<usize>().map_err(|_| ParseError::User {
+        error: (start, "Expected a number on each side of the -"),
+    })
 }
 
 #[test]
@@ -107,7 +199,15 @@ fn parsing_query() {
 #[test]
 fn parsing_location() {
     assert!(location::LocationParser::new().parse("<../../../>").is_ok());
+    assert!(location::LocationParser::new().parse("g<src/**/*.java>").is_ok());
+    assert!(location::LocationParser::new().parse("g<src/main/>&{function}").is_ok());
+    assert!(location::LocationParser::new().parse("<a|(>").is_err());
     assert!(location::LocationParser::new().parse("{function}").is_ok());
+    assert!(location::LocationParser::new().parse("{Outer.method}").is_ok());
+    assert!(location::LocationParser::new().parse("{*.method}").is_ok());
+    assert!(location::LocationParser::new().parse("{test*}").is_ok());
+    assert!(location::LocationParser::new().parse("{/^handle[A-Z]/}").is_ok());
+    assert!(location::LocationParser::new().parse("{/^handle[A-Z/}").is_err());
     assert!(location::LocationParser::new().parse("{function}|l1-5").is_ok());
     assert!(location::LocationParser::new().parse("{function}&l1-5").is_ok());
     assert!(location::LocationParser::new().parse("^{function}|l1-5").is_ok());
@@ -123,4 +223,27 @@ fn parsing_entire() {
     assert!(parse("%s/westoff/Westhoff").is_err());
     assert!(parse("%s/westoff/Westhoff/").is_ok());
     assert!(parse("<mod.rs>s/jon/John/g").is_ok());
+    assert!(parse("<mod.rs>/jon/").is_ok());
+    assert!(parse("%b/jon/").is_err());
+    assert!(parse("{legacyInit}d").is_ok());
+    assert!(parse("<.java>&l0-1i\\// Copyright \\1").is_ok());
+    assert!(parse("{main}dp").is_err());
+    assert!(parse("g<src/**>s|src/main|src/test|g").is_ok());
+    assert!(parse("%s|a\\|b|c|").is_ok());
+    assert_eq!(parse("%s/a|(/b/").unwrap_err().column(), Some(6));
+    assert_eq!(parse("{main}&l-3s/a/b/").unwrap_err().column(), Some(8));
+    assert_eq!(parse("%s/a[[pos=x]]/b/").unwrap_err().column(), Some(10));
+    assert_eq!(parse("%s/a[b-]/c/").unwrap_err().column(), Some(7));
+    assert!(matches!(parse("%s/a$/b/"), Err(Error::Regex { column: 4, .. })));
+    assert!(matches!(parse("%s/a/\\99999999999999999999/"), Err(Error::Replacement { column: 6, .. })));
+}
+
+#[test]
+fn parsing_script() {
+    let commands = parse_script("# rename things\n%s/a/b/g\n\n  {main}s/c/d/\r\n").unwrap();
+    assert_eq!(commands.len(), 2);
+    assert!(!commands[1].global);
+    assert!(parse_script("").unwrap().is_empty());
+    let err = parse_script("%s/a/b/\n%s/a/b\n").unwrap_err().to_string();
+    assert!(err.starts_with("Line 2: "));
 }
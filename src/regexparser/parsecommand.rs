@@ -1,72 +1,325 @@
-use std::error::Error;
-
 use super::ast;
+use crate::error::Error;
 
 /// For parsing out statements of the form
-/// LOCATIONs/REGEX/REPLACEMENT/G
+/// LOCATIONs/REGEX/REPLACEMENT/G, or LOCATION/REGEX/ for searching,
+/// or LOCATIONd, LOCATIONp, LOCATIONi\TEXT and LOCATIONa\TEXT for
+/// deleting, printing, inserting before and appending after lines,
+/// or LOCATIONy/SOURCE/DEST/ for transliterating
 /// # Arguments
 ///
 /// * `text` - A string slice that contains the command to be parsed
 ///
 /// # Returns
 ///
-/// A Result<ReplaceUnparsed, Box<dyn Error>>, where on success, it returns a
+/// A Result<ReplaceUnparsed, Error>, where on success, it returns a
 /// ReplaceUnparsed containing the LOCATION, REGEX, REPLACEMENT, and
 /// whether it is global or not (ends with a g)
-pub fn parse(text: &str) -> Result<ast::ReplaceUnparsed, Box<dyn Error>> {
-    let (location, start) = parse_portion(text, 0)?;
-    if location.chars().last().ok_or("Location empty, expected at least an s")? != 's' {
-        return Err("s expected in location".into());
+pub fn parse(text: &str) -> Result<ast::ReplaceUnparsed, Error> {
+    let (location, command, start, delimiter) = parse_location(text)?;
+    match command {
+        ast::Command::Substitute => parse_substitute(text, location, start, delimiter),
+        ast::Command::Search => parse_search(text, location, start),
+        ast::Command::Delete | ast::Command::Print => Ok(ast::ReplaceUnparsed {
+            command,
+            location,
+            find: String::new(),
+            replace: String::new(),
+            global: true,
+            find_start: start,
+            replace_start: start,
+        }),
+        ast::Command::Insert | ast::Command::Append => Ok(ast::ReplaceUnparsed {
+            command,
+            location,
+            find: String::new(),
+            replace: text[start..].to_string(),
+            global: true,
+            find_start: start,
+            replace_start: start,
+        }),
+        ast::Command::Transliterate(_) => parse_transliterate(text, location, start, delimiter),
     }
-    let (find, start) = parse_portion(text, start)?;
-    let (replace, start) = parse_portion(text, start)?;
-    let rest = &text[start..];
-    let global = if rest.len() == 0 {
-        Ok(false)
-    } else {
-        if rest.len() != 1 {
-            Err("Expected exactly one character after the replacement")
-        } else {
-            if rest.chars().next().ok_or("Really expected at least one last char")? == 'g' {
-                Ok(true)
-            } else {
-                Err("Expected a g")
-            }
+}
+
+/// Parses the rest of a transliterate command of the form LOCATIONy/SOURCE/DEST/,
+/// where the / can be any delimiter, as in LOCATIONy|SOURCE|DEST|
+/// # Arguments
+///
+/// * `text` - A string slice that contains the command to be parsed
+/// * `location` - The location already parsed from the start of the command
+/// * `start` - The index in the string just after the `y/`
+/// * `delimiter` - The character following the `y`
+///
+/// # Returns
+///
+/// A Result<ReplaceUnparsed, Error>, where on success, it returns a
+/// ReplaceUnparsed with each SOURCE character paired with its DEST character.
+/// It will Err if SOURCE and DEST are not the same length.
+fn parse_transliterate(
+    text: &str,
+    location: String,
+    start: usize,
+    delimiter: char,
+) -> Result<ast::ReplaceUnparsed, Error> {
+    let source_start = start;
+    let (source, dest_start) = parse_portion(text, start, delimiter)?;
+    let (dest, start) = parse_portion(text, dest_start, delimiter)?;
+    if start != text.len() {
+        return Err(Error::Command {
+            message: "Expected nothing after the destination of a y command".into(),
+            column: start,
+        });
+    }
+    let (source, dest) = (unescape(&source), unescape(&dest));
+    if source.len() != dest.len() {
+        return Err(Error::Command {
+            message: "Expected the source and destination of a y command to be the same length".into(),
+            column: dest_start,
+        });
+    }
+    Ok(ast::ReplaceUnparsed {
+        command: ast::Command::Transliterate(source.into_iter().zip(dest).collect()),
+        location,
+        find: String::new(),
+        replace: String::new(),
+        global: true,
+        find_start: source_start,
+        replace_start: dest_start,
+    })
+}
+
+/// Unescapes the characters of a portion, where `\n` is a newline
+/// and a backslash before any other character is that character
+fn unescape(text: &str) -> Vec<char> {
+    let mut ret = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        ret.push(match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('n') => '\n',
+                Some(x) => x,
+                None => '\\',
+            },
+            (c, false) => c,
+        });
+    }
+    ret
+}
+
+/// Parses the rest of a substitute command of the form LOCATIONs/REGEX/REPLACEMENT/G,
+/// where the / can be any delimiter, as in LOCATIONs|REGEX|REPLACEMENT|G
+/// # Arguments
+///
+/// * `text` - A string slice that contains the command to be parsed
+/// * `location` - The location already parsed from the start of the command
+/// * `start` - The index in the string just after the `s/`
+/// * `delimiter` - The character following the `s`
+///
+/// # Returns
+///
+/// A Result<ReplaceUnparsed, Error>, where on success, it returns a
+/// ReplaceUnparsed containing the LOCATION, REGEX, REPLACEMENT, and
+/// whether it is global or not (ends with a g)
+fn parse_substitute(
+    text: &str,
+    location: String,
+    start: usize,
+    delimiter: char,
+) -> Result<ast::ReplaceUnparsed, Error> {
+    let find_start = start;
+    let (find, replace_start) = parse_portion(text, start, delimiter)?;
+    let (replace, start) = parse_portion(text, replace_start, delimiter)?;
+    let replace = unescape_delimiter(&replace, delimiter);
+    let global = match &text[start..] {
+        "" => false,
+        "g" => true,
+        _ => {
+            return Err(Error::Command {
+                message: "Expected nothing or a g after the replacement".into(),
+                column: start,
+            })
         }
-    }?;
+    };
     Ok(ast::ReplaceUnparsed {
-        location: location[..location.len() - 1].to_string(),
+        command: ast::Command::Substitute,
+        location,
         find,
         replace,
         global,
+        find_start,
+        replace_start,
+    })
+}
+
+/// Parses the rest of a search command of the form LOCATION/REGEX/
+/// # Arguments
+///
+/// * `text` - A string slice that contains the command to be parsed
+/// * `location` - The location already parsed from the start of the command
+/// * `start` - The index in the string just after the location
+///
+/// # Returns
+///
+/// A Result<ReplaceUnparsed, Error>, where on success, it returns a
+/// ReplaceUnparsed with an empty REPLACEMENT
+fn parse_search(
+    text: &str,
+    location: String,
+    start: usize,
+) -> Result<ast::ReplaceUnparsed, Error> {
+    let find_start = start;
+    let (find, start) = parse_portion(text, start, '/')?;
+    if start != text.len() {
+        return Err(Error::Command {
+            message: "Expected nothing after the regex of a search".into(),
+            column: start,
+        });
+    }
+    Ok(ast::ReplaceUnparsed {
+        command: ast::Command::Search,
+        location,
+        find,
+        replace: String::new(),
+        global: true,
+        find_start,
+        replace_start: start,
+    })
+}
+
+/// Unescapes the delimiter within the replacement of a substitute command, so that
+/// `s|a|x\|y|` replaces with `x|y`, leaving every other escape alone
+fn unescape_delimiter(text: &str, delimiter: char) -> String {
+    let mut ret = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some(x) if x == delimiter => ret.push(x),
+                Some(x) => {
+                    ret.push(c);
+                    ret.push(x);
+                }
+                None => ret.push(c),
+            }
+        } else {
+            ret.push(c);
+        }
+    }
+    ret
+}
+
+/// Checks whether a character can be used as the delimiter of an `s` or `y` command
+fn is_delimiter(c: char) -> bool {
+    !c.is_alphanumeric() && !c.is_whitespace() && c != '\\'
+}
+
+/// Parses the location at the start of a command, up until the command
+/// itself, which is the first `s/`, `y/`, `/`, `i\`, or `a\`, or a `d` or `p`
+/// ending the command, that is not within a path like `<src/>` or
+/// a function like `{/^handle/}`, and does not follow a letter.
+/// The `/` after an `s` or `y` can be any other delimiter, like `s|`.
+/// # Arguments
+///
+/// * `text` - A string slice that contains the command to be parsed
+///
+/// # Returns
+///
+/// A Result<(String, Command, usize, char), Error>, where on success, it
+/// returns a tuple containing the parsed location, the command, the byte offset of where
+/// to start for future parsing, and the delimiter of the command.
+fn parse_location(text: &str) -> Result<(String, ast::Command, usize, char), Error> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut close = None;
+    // Where the body of the regex of a {/regex/} location starts, while within it,
+    // since the body may hold a } or any escaped character
+    let mut regex = None;
+    let mut escaped = false;
+    for (i, c) in chars.iter().enumerate() {
+        if let Some(body) = regex {
+            if i >= body {
+                if escaped {
+                    escaped = false;
+                } else if *c == '\\' {
+                    escaped = true;
+                } else if *c == '/' {
+                    regex = None;
+                }
+            }
+            continue;
+        }
+        let next = chars.get(i + 1).copied();
+        let after_letter = i > 0 && chars[i - 1].is_alphabetic();
+        let (command, len) = match (close, c, next) {
+            (Some(x), _, _) if x == *c => {
+                close = None;
+                continue;
+            }
+            (Some(_), _, _) => continue,
+            (None, '<', _) => {
+                close = Some('>');
+                continue;
+            }
+            (None, '{', Some('/')) => {
+                close = Some('}');
+                regex = Some(i + 2);
+                continue;
+            }
+            (None, '{', _) => {
+                close = Some('}');
+                continue;
+            }
+            (None, '/', _) => (ast::Command::Search, 1),
+            _ if after_letter => continue,
+            (None, 's', Some(d)) if is_delimiter(d) => (ast::Command::Substitute, 2),
+            (None, 'y', Some(d)) if is_delimiter(d) => (ast::Command::Transliterate(vec![]), 2),
+            (None, 'd', None) => (ast::Command::Delete, 1),
+            (None, 'p', None) => (ast::Command::Print, 1),
+            (None, 'i', Some('\\')) => (ast::Command::Insert, 2),
+            (None, 'a', Some('\\')) => (ast::Command::Append, 2),
+            _ => continue,
+        };
+        let location: String = chars[..i].iter().collect();
+        let delimiter = chars[i + len - 1];
+        let start = location.len() + chars[i..i + len].iter().map(|c| c.len_utf8()).sum::<usize>();
+        return Ok((location, command, start, delimiter));
+    }
+    Err(Error::Command {
+        message: "Did not find a command after the location, expected one of s/, y/, /, d, p, i\\ or a\\".into(),
+        column: text.len(),
     })
 }
 
-/// Parses text until it finds an unescaped / chracter
+/// Parses text until it finds an unescaped delimiter
 /// # Arguments
 ///
 /// * `text` - A string slice that contains the command to be parsed
 /// * `start` - The index in the string to start from
+/// * `delimiter` - The character that ends the portion, usually /
 ///
 /// # Returns
 ///
-/// A Result<(String, usize), Box<dyn Error>>, where on success, it returns a
+/// A Result<(String, usize), Error>, where on success, it returns a
 /// tuple containing the parsed string and the index of where to start
 /// for future parsing.
-fn parse_portion(text: &str, start: usize) -> Result<(String, usize), Box<dyn Error>> {
+fn parse_portion(text: &str, start: usize, delimiter: char) -> Result<(String, usize), Error> {
     let mut escape = false;
-    for (i, c) in text.chars().enumerate().skip(start) {
+    for (i, c) in text[start..].char_indices().map(|(i, c)| (start + i, c)) {
         match c {
             '\\' => escape = !escape,
-            '/' => {
+            c if c == delimiter => {
                 if !escape {
-                    return Ok((text[start..i].to_string(), i + 1));
+                    return Ok((text[start..i].to_string(), i + c.len_utf8()));
                 }
+                escape = false;
             }
             _ => escape = false,
         }
     }
-    Err("Did not find an unescaped backslash!".into())
+    Err(Error::Command {
+        message: format!("Did not find an unescaped {}!", delimiter),
+        column: text.len(),
+    })
 }
 
 #[test]
@@ -88,4 +341,60 @@ fn parsing_command() {
     assert_eq!(x.find, "jon");
     assert_eq!(x.replace, "John");
     assert_eq!(x.global, true);
+    let x = parse("%/[[type=Session]]/").unwrap();
+    assert_eq!(x.command, ast::Command::Search);
+    assert_eq!(x.location, "%");
+    assert_eq!(x.find, "[[type=Session]]");
+    assert!(parse("%/a/b/").is_err());
+    assert!(parse("%b/a/b/").is_err());
+    let x = parse("{legacyInit}d").unwrap();
+    assert_eq!(x.command, ast::Command::Delete);
+    assert_eq!(x.location, "{legacyInit}");
+    let x = parse("<src/>&l0-3p").unwrap();
+    assert_eq!(x.command, ast::Command::Print);
+    assert_eq!(x.location, "<src/>&l0-3");
+    let x = parse("<.java>l0-1i\\// Copyright").unwrap();
+    assert_eq!(x.command, ast::Command::Insert);
+    assert_eq!(x.location, "<.java>l0-1");
+    assert_eq!(x.replace, "// Copyright");
+    let x = parse("{main}a\\").unwrap();
+    assert_eq!(x.command, ast::Command::Append);
+    assert_eq!(x.replace, "");
+    assert!(parse("{main}").is_err());
+    let x = parse("{main}y/ab\\//\\n\\\\-/").unwrap();
+    assert_eq!(x.location, "{main}");
+    assert_eq!(
+        x.command,
+        ast::Command::Transliterate(vec![('a', '\n'), ('b', '\\'), ('/', '-')])
+    );
+    assert!(parse("%y/abc/xy/").is_err());
+    assert!(parse("%y/abc/xyz/g").is_err());
+    let x = parse("g<src/**>s|src/main|src\\|test|g").unwrap();
+    assert_eq!(x.location, "g<src/**>");
+    assert_eq!(x.find, "src/main");
+    assert_eq!(x.replace, "src|test");
+    assert!(x.global);
+    let x = parse("%s#a\\/b#\\1\\/#").unwrap();
+    assert_eq!(x.find, "a\\/b");
+    assert_eq!(x.replace, "\\1\\/");
+    let x = parse("{main}y,ab,\\,c,").unwrap();
+    assert_eq!(x.command, ast::Command::Transliterate(vec![('a', ','), ('b', 'c')]));
+    assert!(parse("%s a b ").is_err());
+    assert_eq!(parse("%s/a/b").unwrap_err().column(), Some(6));
+    assert_eq!(parse("%s/a/b/x").unwrap_err().column(), Some(7));
+    let x = parse("%s/abc/d/").unwrap();
+    assert_eq!((x.find_start, x.replace_start), (3, 7));
+    let x = parse("{é}s/é/e/").unwrap();
+    assert_eq!((x.location.as_str(), x.find.as_str(), x.replace.as_str()), ("{é}", "é", "e"));
+    assert_eq!(parse("{é}s/é/e/x").unwrap_err().column(), Some(11));
+    let x = parse("{/^handle/}|<src/main/>s/a/b/").unwrap();
+    assert_eq!(x.command, ast::Command::Substitute);
+    assert_eq!(x.location, "{/^handle/}|<src/main/>");
+    assert_eq!(x.find, "a");
+    let x = parse("{/x}/}s/a/b/").unwrap();
+    assert_eq!(x.command, ast::Command::Substitute);
+    assert_eq!(x.location, "{/x}/}");
+    let x = parse("{/a\\/}/}d").unwrap();
+    assert_eq!(x.command, ast::Command::Delete);
+    assert_eq!(x.location, "{/a\\/}/}");
 }
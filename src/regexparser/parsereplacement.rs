@@ -1,6 +1,5 @@
-use std::error::Error;
-
 use super::ast::{ReplaceItem, Replacement};
+use crate::error::Error;
 
 /// For parsing out the replacement form of a command
 /// # Arguments
@@ -9,10 +8,10 @@ use super::ast::{ReplaceItem, Replacement};
 ///
 /// # Returns
 ///
-/// A Result<Replacement, Box<dyn Error>>, where on success, it returns a
+/// A Result<Replacement, Error>, where on success, it returns a
 /// Replacement containing the set of ReplaceItems
 /// that make up the new replacement string
-pub fn parse(text: &str) -> Result<Replacement, Box<dyn Error>> {
+pub fn parse(text: &str) -> Result<Replacement, Error> {
     let mut v = Vec::new();
     let mut i = 0;
     while i < text.len() {
@@ -31,12 +30,16 @@ pub fn parse(text: &str) -> Result<Replacement, Box<dyn Error>> {
 ///
 /// # Returns
 ///
-/// A Result<(ReplaceItem, usize), Box<dyn Error>>, where on success, it returns a
+/// A Result<(ReplaceItem, usize), Error>, where on success, it returns a
 /// tuple containing the parsed ReplaceItem and the index of where to start
 /// for future parsing.
-fn parse_item(text: &str, start: usize) -> Result<(ReplaceItem, usize), Box<dyn Error>> {
+fn parse_item(text: &str, start: usize) -> Result<(ReplaceItem, usize), Error> {
     let mut chars = text.chars().enumerate().skip(start);
-    if chars.next().ok_or("Out of characters")?.1 == '\\' {
+    let first = chars.next().ok_or_else(|| Error::Replacement {
+        message: "Out of characters".into(),
+        column: start,
+    })?;
+    if first.1 == '\\' {
         // We might be parsing a backreference
         let mut last = 0;
         while let Some((i, c)) = chars.next() {
@@ -48,7 +51,10 @@ fn parse_item(text: &str, start: usize) -> Result<(ReplaceItem, usize), Box<dyn
         }
         if last != 0 {
             return Ok((
-                ReplaceItem::BackRef(text[start + 1..last+1].parse::<usize>()?),
+                ReplaceItem::BackRef(text[start + 1..last + 1].parse::<usize>().map_err(|x| Error::Replacement {
+                    message: format!("Invalid backreference: {}", x),
+                    column: start + 1,
+                })?),
                 last + 1,
             ));
         }
@@ -66,7 +72,7 @@ fn parse_item(text: &str, start: usize) -> Result<(ReplaceItem, usize), Box<dyn
 }
 
 #[test]
-fn parsing_replacement() -> Result<(), Box<dyn Error>>{
+fn parsing_replacement() -> Result<(), Box<dyn std::error::Error>>{
     let parsed = parse("bob\\\\\\13dole")?;
     if let ReplaceItem::String(s) = parsed.replacements.get(0).ok_or("sad")? {
         assert_eq!(s, "bob");
@@ -0,0 +1,201 @@
+use std::{
+    fs,
+    io::Write,
+    path::Path,
+    process::{Command, Output, Stdio},
+};
+
+/// Runs the spidior binary within a directory
+fn spidior(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_spidior"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .expect("spidior should run")
+}
+
+/// Reads the actions recorded in the journal of a directory, without the run they belong to
+fn journal_actions(dir: &Path) -> Vec<serde_json::Value> {
+    fs::read_to_string(dir.join(".spidior.journal"))
+        .unwrap_or_default()
+        .lines()
+        .map(|line| {
+            let mut entry: serde_json::Value = serde_json::from_str(line).unwrap();
+            entry.as_object_mut().unwrap().remove("run");
+            entry
+        })
+        .collect()
+}
+
+#[test]
+fn test_jobs_keep_order() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    for i in 0..100 {
+        let sub = dir.path().join(format!("d{}", i % 7));
+        fs::create_dir_all(&sub)?;
+        fs::write(sub.join(format!("f{}.c", i)), format!("int foo = {};\nfoo++;\n", i))?;
+    }
+    // Every run uses the same tree, so that the files are found in the same order
+    let mut runs = Vec::new();
+    for jobs in ["1", "8"] {
+        let printed = spidior(dir.path(), &["-r", "-j", jobs, "-q", "%s/foo/bar/g"]);
+        let edited = spidior(dir.path(), &["-r", "-j", jobs, "-i", "-q", "%s/foo/bar/g"]);
+        assert!(printed.status.success() && edited.status.success());
+        runs.push((printed.stdout, edited.stderr, journal_actions(dir.path())));
+        assert!(spidior(dir.path(), &["undo"]).status.success());
+    }
+    assert_eq!(runs[0].2.len(), 100);
+    assert_eq!(runs[0], runs[1]);
+    Ok(())
+}
+
+#[test]
+fn test_unchanged_file_is_left_alone() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let (a, b) = (dir.path().join("a.c"), dir.path().join("b.c"));
+    fs::write(&a, "int foo;\n")?;
+    fs::write(&b, "int bar;\n")?;
+    // The file matches, but the replacement leaves it as it was
+    let mtime = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+    fs::File::options().write(true).open(&b)?.set_modified(mtime)?;
+
+    let output = spidior(dir.path(), &["-i", "-q", "%s/foo|bar/bar/g"]);
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&a)?, "int bar;\n");
+    assert_eq!(fs::metadata(&b)?.modified()?, mtime);
+    let stderr = String::from_utf8(output.stderr)?;
+    let listed = stderr.split_once("Modified 1 file(s):\n").map(|(_, rest)| rest);
+    assert_eq!(listed, Some("  ./a.c\n"));
+    Ok(())
+}
+
+#[test]
+fn test_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let run = |args: &[&str]| -> Result<String, Box<dyn std::error::Error>> {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_spidior"))
+            .current_dir(dir.path())
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        child.stdin.take().unwrap().write_all(b"int foo;\nfoo++;\n")?;
+        let output = child.wait_with_output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?)
+    };
+    let query = "<src/.*\\.java>s/foo/bar/g";
+    assert_eq!(
+        run(&["-p", "-", "--stdin-filename", "src/Foo.java", "-q", query])?,
+        "int bar;\nbar++;\n"
+    );
+    assert_eq!(
+        run(&["-p", "-", "--stdin-filename", "test/Foo.c", "-q", query])?,
+        "int foo;\nfoo++;\n"
+    );
+    assert_eq!(run(&["-p", "-", "-q", query])?, "int foo;\nfoo++;\n");
+    assert!(fs::read_dir(dir.path())?.next().is_none());
+    Ok(())
+}
+
+#[test]
+fn test_globs_match_relative_to_path() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let proj = dir.path().join("proj");
+    for file in ["src/main/A.java", "src/B.java", "test/src/C.java"] {
+        let path = proj.join(file);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, "int foo;\n")?;
+    }
+    let absolute = proj.to_string_lossy().to_string();
+    for root in ["proj", "proj/", absolute.as_str()] {
+        for (query, edited) in [
+            ("g<src/**/*.java>s/foo/bar/g", vec!["src/main/A.java", "src/B.java"]),
+            ("g<src/main/>s/foo/bar/g", vec!["src/main/A.java"]),
+            ("g<*.java>s/foo/bar/g", vec!["src/main/A.java", "src/B.java", "test/src/C.java"]),
+        ] {
+            assert!(spidior(dir.path(), &["-r", "-p", root, "-i", "-q", query]).status.success());
+            for file in ["src/main/A.java", "src/B.java", "test/src/C.java"] {
+                let expected = if edited.contains(&file) { "int bar;\n" } else { "int foo;\n" };
+                assert_eq!(fs::read_to_string(proj.join(file))?, expected, "{} with -p {}", query, root);
+            }
+            assert!(spidior(dir.path(), &["undo"]).status.success());
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_diff_rename_leaves_tree_alone() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("A.java"), "int foo;\n")?;
+    let output = spidior(dir.path(), &["--diff", "-q", "g<*.java>s/foo/bar/g", "-R", "\\1_renamed.java"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "diff --git a/A.java b/A_renamed.java\nrename from A.java\nrename to A_renamed.java\n\
+         --- a/A.java\n+++ b/A_renamed.java\n@@ -1 +1 @@\n-int foo;\n+int bar;\n"
+    );
+    let files: Vec<_> = fs::read_dir(dir.path())?.map(|e| e.map(|e| e.file_name())).collect::<Result<_, _>>()?;
+    assert_eq!(files, vec!["A.java"]);
+    assert_eq!(fs::read_to_string(dir.path().join("A.java"))?, "int foo;\n");
+    Ok(())
+}
+
+#[test]
+fn test_json_rename_reports_without_renaming() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("A.java"), "int foo;\n")?;
+    let args = ["--json", "-q", "g<*.java>s/foo/bar/g", "-R", "\\1_renamed.java"];
+    let output = spidior(dir.path(), &args);
+    assert!(output.status.success());
+    let file: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(file["renamed"], "./A_renamed.java");
+    assert_eq!(fs::read_to_string(dir.path().join("A.java"))?, "int foo;\n");
+    assert!(!dir.path().join("A_renamed.java").exists());
+
+    let output = spidior(dir.path(), &[&args[..], &["-i"]].concat());
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(dir.path().join("A_renamed.java"))?, "int bar;\n");
+    assert!(!dir.path().join("A.java").exists());
+    Ok(())
+}
+
+#[test]
+fn test_walker_filters() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    for (file, contents) in [
+        (".gitignore", "target/\n"),
+        ("src/a.c", "int foo;\n"),
+        ("src/b.java", "int foo;\n"),
+        ("target/t.c", "int foo;\n"),
+        (".hidden/h.c", "int foo;\n"),
+        ("bin.c", "int foo;\0\n"),
+    ] {
+        let path = dir.path().join(file);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, contents)?;
+    }
+    // Lists the files a dry run found matches in
+    let visited = |flags: &[&str]| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let output = spidior(dir.path(), &[&["-r", "--dry-run", "-q", "%s/foo/bar/g"], flags].concat());
+        let mut files: Vec<String> = String::from_utf8(output.stdout)?
+            .lines()
+            .filter_map(|line| line.split_once(": "))
+            .map(|(path, _)| path.to_string())
+            .filter(|path| path != "total")
+            .collect();
+        files.sort();
+        Ok(files)
+    };
+    assert_eq!(visited(&[])?, vec!["./src/a.c", "./src/b.java"]);
+    assert_eq!(visited(&["--no-ignore"])?, vec!["./src/a.c", "./src/b.java", "./target/t.c"]);
+    assert_eq!(visited(&["--hidden"])?, vec!["./.hidden/h.c", "./src/a.c", "./src/b.java"]);
+    assert_eq!(visited(&["--include", "*.c"])?, vec!["./src/a.c"]);
+    assert_eq!(visited(&["--exclude", "src/"])?, Vec::<String>::new());
+    assert_eq!(
+        visited(&["--hidden", "--no-ignore", "--exclude", "*.java"])?,
+        vec!["./.hidden/h.c", "./src/a.c", "./target/t.c"]
+    );
+    Ok(())
+}